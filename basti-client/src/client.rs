@@ -1,17 +1,23 @@
-use std::time::Duration;
+use std::{sync::Mutex, time::Duration};
 
 use anyhow::bail;
-use reqwest::{Method, RequestBuilder};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use url::Url;
 use uuid::Uuid;
 
-use basti_types::{CreateTask, Task, TaskPriority, TaskState};
+use basti_types::{
+    BatchOutcome, ConcurrencyGroup, ConcurrencyLimit, CreateTask, ListTasks, NodeCapacity,
+    NodeStatus, QueueStats, QueueStatus, RateLimit, ScaleWorkers, SetConcurrencyLimit,
+    SetRateLimit, Task, TaskLogs, TaskPage, TaskPriority, TaskResult, UpdateTask,
+};
 
 #[derive(Debug)]
 pub struct Client {
     endpoints: Vec<Url>,
     http_client: reqwest::Client,
+    /// Index of the endpoint which last served a request, tried first.
+    last_endpoint: Mutex<usize>,
 }
 
 impl Client {
@@ -23,6 +29,7 @@ impl Client {
                 .connect_timeout(Duration::from_secs(3))
                 .timeout(Duration::from_secs(3))
                 .build()?,
+            last_endpoint: Mutex::new(0),
         })
     }
 
@@ -31,60 +38,178 @@ impl Client {
         T: DeserializeOwned,
         F: Fn(Url) -> RequestBuilder,
     {
-        for url in &self.endpoints {
-            let request = make_request(url.clone()).build()?;
+        Ok(self.send(make_request).await?.json().await?)
+    }
 
-            let Ok(response) = self.http_client.execute(request).await else {
+    /// Sends a request, starting with the endpoint which served the last one.
+    /// Once an endpoint can't be reached or is unavailable, the others are
+    /// only tried if they are ready.
+    async fn send<F>(&self, make_request: F) -> anyhow::Result<Response>
+    where
+        F: Fn(Url) -> RequestBuilder,
+    {
+        let last_endpoint = *self.last_endpoint.lock().unwrap();
+        let indices =
+            (0..self.endpoints.len()).map(|offset| (last_endpoint + offset) % self.endpoints.len());
+
+        let mut failed = false;
+        for index in indices {
+            let url = &self.endpoints[index];
+            if failed && !self.is_ready(url).await {
                 continue;
+            }
+
+            let request = make_request(url.clone()).build()?;
+
+            let response = match self.http_client.execute(request).await {
+                Ok(response) if response.status() != StatusCode::SERVICE_UNAVAILABLE => response,
+                _ => {
+                    failed = true;
+                    continue;
+                }
             };
 
+            *self.last_endpoint.lock().unwrap() = index;
+
             if !response.status().is_success() {
                 bail!("{}", response.text().await?)
             }
 
-            return Ok(response.json().await?);
+            return Ok(response);
         }
 
-        bail!("All API endpoints are dead");
+        bail!("No API endpoint is ready");
+    }
+
+    /// Whether the node behind an endpoint can reach etcd and isn't draining.
+    /// Nodes without a readiness check are assumed to be ready.
+    async fn is_ready(&self, url: &Url) -> bool {
+        let mut url = url.clone();
+        url.set_path("/readyz");
+
+        self.http_client
+            .request(Method::GET, url)
+            .send()
+            .await
+            .is_ok_and(|response| {
+                response.status().is_success() || response.status() == StatusCode::NOT_FOUND
+            })
     }
 
-    pub async fn submit(&self, duration: Duration, priority: TaskPriority) -> anyhow::Result<Task> {
-        let payload = CreateTask { duration, priority };
+    /// Submits a task. Tasks without a dedupe key get a random idempotency
+    /// key, so retries against other endpoints don't create duplicates.
+    pub async fn submit(&self, payload: CreateTask) -> anyhow::Result<Task> {
+        let idempotency_key = Uuid::new_v4().to_string();
         self.execute(|mut url| {
             url.set_path("/api/tasks");
-            self.http_client.request(Method::POST, url).json(&payload)
+            let request = self.http_client.request(Method::POST, url).json(&payload);
+            if payload.dedupe_key.is_none() {
+                request.header("Idempotency-Key", &idempotency_key)
+            } else {
+                request
+            }
         })
         .await
     }
 
-    pub async fn list(
+    pub async fn submit_batch(
         &self,
-        state: Option<TaskState>,
-        limit: Option<u32>,
-    ) -> anyhow::Result<Vec<Task>> {
+        mut payload: Vec<CreateTask>,
+    ) -> anyhow::Result<Vec<BatchOutcome>> {
+        for task in &mut payload {
+            task.dedupe_key
+                .get_or_insert_with(|| Uuid::new_v4().to_string());
+        }
+
         self.execute(|mut url| {
-            url.set_path("/api/tasks");
+            url.set_path("/api/tasks/batch");
+            self.http_client.request(Method::POST, url).json(&payload)
+        })
+        .await
+    }
 
-            if let Some(state) = &state {
-                url.query_pairs_mut()
-                    .append_pair("state", &state.to_string());
-            }
+    pub async fn list(&self, query: &ListTasks) -> anyhow::Result<TaskPage> {
+        let response = self
+            .send(|mut url| {
+                url.set_path("/api/tasks");
+                self.http_client.request(Method::GET, url).query(query)
+            })
+            .await?;
+
+        let cursor = match response.headers().get(TaskPage::CURSOR_HEADER) {
+            Some(cursor) => Some(cursor.to_str()?.to_string()),
+            None => None,
+        };
+
+        Ok(TaskPage {
+            tasks: response.json().await?,
+            cursor,
+        })
+    }
+
+    pub async fn find(&self, id: Uuid) -> anyhow::Result<Task> {
+        let path = format!("/api/tasks/{id}");
+        self.execute::<Task, _>(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
 
-            if let Some(limit) = &limit {
+    pub async fn logs(
+        &self,
+        id: Uuid,
+        after: Option<u32>,
+        follow: bool,
+    ) -> anyhow::Result<TaskLogs> {
+        let path = format!("/api/tasks/{id}/logs");
+        self.execute(|mut url| {
+            url.set_path(&path);
+            if let Some(after) = after {
                 url.query_pairs_mut()
-                    .append_pair("limit", &limit.to_string());
+                    .append_pair("after", &after.to_string());
             }
+            if follow {
+                url.query_pairs_mut().append_pair("follow", "true");
+            }
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
 
+    pub async fn result(&self, id: Uuid) -> anyhow::Result<TaskResult> {
+        let path = format!("/api/tasks/{id}/result");
+        self.execute(|mut url| {
+            url.set_path(&path);
             self.http_client.request(Method::GET, url)
         })
         .await
     }
 
-    pub async fn find(&self, id: Uuid) -> anyhow::Result<Task> {
+    pub async fn reprioritize(&self, id: Uuid, priority: TaskPriority) -> anyhow::Result<Task> {
         let path = format!("/api/tasks/{id}");
+        let payload = UpdateTask { priority };
         self.execute::<Task, _>(|mut url| {
             url.set_path(&path);
-            self.http_client.request(Method::GET, url)
+            self.http_client.request(Method::PATCH, url).json(&payload)
+        })
+        .await
+    }
+
+    pub async fn pause(&self, id: Uuid) -> anyhow::Result<Task> {
+        let path = format!("/api/tasks/{id}/pause");
+        self.execute::<Task, _>(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::POST, url)
+        })
+        .await
+    }
+
+    pub async fn resume(&self, id: Uuid) -> anyhow::Result<Task> {
+        let path = format!("/api/tasks/{id}/resume");
+        self.execute::<Task, _>(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::POST, url)
         })
         .await
     }
@@ -97,4 +222,118 @@ impl Client {
         })
         .await
     }
+
+    pub async fn queue_status(&self) -> anyhow::Result<QueueStatus> {
+        self.execute(|mut url| {
+            url.set_path("/api/queue");
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
+
+    pub async fn queue_stats(&self) -> anyhow::Result<QueueStats> {
+        self.execute(|mut url| {
+            url.set_path("/api/stats");
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
+
+    pub async fn pause_queue(&self) -> anyhow::Result<QueueStatus> {
+        self.execute(|mut url| {
+            url.set_path("/api/queue/pause");
+            self.http_client.request(Method::POST, url)
+        })
+        .await
+    }
+
+    pub async fn resume_queue(&self) -> anyhow::Result<QueueStatus> {
+        self.execute(|mut url| {
+            url.set_path("/api/queue/resume");
+            self.http_client.request(Method::POST, url)
+        })
+        .await
+    }
+
+    pub async fn list_concurrency_limits(&self) -> anyhow::Result<Vec<ConcurrencyLimit>> {
+        self.execute(|mut url| {
+            url.set_path("/api/concurrency");
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
+
+    pub async fn set_concurrency_limit(
+        &self,
+        group: &ConcurrencyGroup,
+        limit: u32,
+    ) -> anyhow::Result<ConcurrencyLimit> {
+        let path = format!("/api/concurrency/{group}");
+        let payload = SetConcurrencyLimit { limit };
+        self.execute(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::PUT, url).json(&payload)
+        })
+        .await
+    }
+
+    pub async fn remove_concurrency_limit(
+        &self,
+        group: &ConcurrencyGroup,
+    ) -> anyhow::Result<ConcurrencyLimit> {
+        let path = format!("/api/concurrency/{group}");
+        self.execute(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::DELETE, url)
+        })
+        .await
+    }
+
+    pub async fn list_rate_limits(&self) -> anyhow::Result<Vec<RateLimit>> {
+        self.execute(|mut url| {
+            url.set_path("/api/rate-limits");
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
+
+    pub async fn set_rate_limit(
+        &self,
+        group: &ConcurrencyGroup,
+        payload: SetRateLimit,
+    ) -> anyhow::Result<RateLimit> {
+        let path = format!("/api/rate-limits/{group}");
+        self.execute(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::PUT, url).json(&payload)
+        })
+        .await
+    }
+
+    pub async fn remove_rate_limit(&self, group: &ConcurrencyGroup) -> anyhow::Result<RateLimit> {
+        let path = format!("/api/rate-limits/{group}");
+        self.execute(|mut url| {
+            url.set_path(&path);
+            self.http_client.request(Method::DELETE, url)
+        })
+        .await
+    }
+
+    pub async fn node_status(&self) -> anyhow::Result<NodeStatus> {
+        self.execute(|mut url| {
+            url.set_path("/api/node");
+            self.http_client.request(Method::GET, url)
+        })
+        .await
+    }
+
+    pub async fn scale_workers(&self, workers: u32) -> anyhow::Result<NodeCapacity> {
+        self.execute(|mut url| {
+            url.set_path("/api/node/workers");
+            self.http_client
+                .request(Method::PUT, url)
+                .json(&ScaleWorkers { workers })
+        })
+        .await
+    }
 }
@@ -1,10 +1,20 @@
-use std::{cmp::Ordering, time::Duration};
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
 
-use clap::Args;
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
 use colored::Colorize;
 use uuid::Uuid;
 
-use basti_types::{TaskPriority, TaskState};
+use basti_types::{
+    BatchOutcome, ConcurrencyGroup, ConflictPolicy, CreateTask, Labels, ListTasks, LogStream,
+    Resources, SetRateLimit, TaskOutcome, TaskPriority, TaskSort, TaskState, WorkerName,
+};
 
 use crate::{
     client::Client,
@@ -12,6 +22,10 @@ use crate::{
     util::{self, Compact},
 };
 
+fn parse_payload(s: &str) -> anyhow::Result<serde_json::Value> {
+    Ok(serde_json::from_str(s)?)
+}
+
 #[derive(Debug, Args)]
 pub struct SubmitArgs {
     #[clap(long, default_value_t = 10, help = "Task duration in seconds")]
@@ -28,21 +42,161 @@ pub struct SubmitArgs {
         help = "Task priority, 0 = highest priority"
     )]
     priority: TaskPriority,
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Number of identical tasks to submit"
+    )]
+    count: usize,
+    #[clap(
+        long,
+        help = "Key of which only one unfinished task may exist at a time"
+    )]
+    unique_key: Option<String>,
+    #[clap(
+        long,
+        requires = "unique_key",
+        help = "Return the existing task instead of failing on unique key conflicts"
+    )]
+    coalesce: bool,
+    #[clap(long, help = "Group whose concurrency limit applies to the task")]
+    concurrency_group: Option<ConcurrencyGroup>,
+    #[clap(
+        long,
+        default_value = "",
+        help = "Comma-separated key=value labels a worker needs to run the task"
+    )]
+    selector: Labels,
+    #[clap(long, default_value_t = 1, help = "CPU units the task requires")]
+    cpu: u32,
+    #[clap(long, default_value_t = 0, help = "Memory units the task requires")]
+    memory: u32,
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "Interval at which the task reports progress, e.g. 2s"
+    )]
+    heartbeat_interval: Option<Duration>,
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "Time without progress after which the task is requeued, e.g. 30s"
+    )]
+    stale_timeout: Option<Duration>,
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "Maximum time the task may take once started, e.g. 1m"
+    )]
+    timeout: Option<Duration>,
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        help = "Time from now after which the task fails if it hasn't started, e.g. 10m"
+    )]
+    deadline: Option<Duration>,
+    #[clap(
+        long,
+        value_parser = parse_payload,
+        help = "JSON input of the task"
+    )]
+    payload: Option<serde_json::Value>,
+    #[clap(
+        long,
+        default_value = "",
+        help = "Comma-separated key=value labels to attach to the task"
+    )]
+    label: Labels,
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "seconds",
+            "millis",
+            "priority",
+            "count",
+            "unique_key",
+            "concurrency_group",
+            "selector",
+            "cpu",
+            "memory",
+            "heartbeat_interval",
+            "stale_timeout",
+            "timeout",
+            "deadline",
+            "payload",
+            "label",
+        ],
+        help = "Submit tasks from a JSON file containing an array of tasks"
+    )]
+    from_file: Option<PathBuf>,
 }
 
 pub async fn submit_command(args: SubmitArgs, client: Client) -> anyhow::Result<()> {
-    let task = client
-        .submit(
-            Duration::from_secs(args.seconds) + Duration::from_millis(args.millis),
-            args.priority,
-        )
-        .await?;
+    let payload = match args.from_file {
+        Some(path) => serde_json::from_slice(&fs::read(path)?)?,
+        None => vec![
+            CreateTask {
+                duration: Duration::from_secs(args.seconds) + Duration::from_millis(args.millis),
+                priority: args.priority,
+                dedupe_key: None,
+                unique_key: args.unique_key,
+                on_conflict: if args.coalesce {
+                    ConflictPolicy::Coalesce
+                } else {
+                    ConflictPolicy::Reject
+                },
+                concurrency_group: args.concurrency_group,
+                selector: args.selector,
+                resources: Resources::new(args.cpu, args.memory),
+                heartbeat_interval: args.heartbeat_interval,
+                stale_timeout: args.stale_timeout,
+                timeout: args.timeout,
+                deadline: args.deadline.map(|deadline| Utc::now() + deadline),
+                payload: args.payload,
+                labels: args.label,
+            };
+            args.count
+        ],
+    };
 
-    println!(
-        "{} Created task {}",
-        "✓".green().bold(),
-        task.key.id.to_string().bright_black().italic()
-    );
+    if let [task] = payload.as_slice() {
+        let task = client.submit(task.clone()).await?;
+
+        println!(
+            "{} Created task {}",
+            "✓".green().bold(),
+            task.key.id.to_string().bright_black().italic()
+        );
+
+        return Ok(());
+    }
+
+    let outcomes = client.submit_batch(payload).await?;
+
+    let created = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, BatchOutcome::Created { .. }))
+        .count();
+    println!("{} Created {} tasks", "✓".green().bold(), created);
+
+    let existing = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, BatchOutcome::Existing { .. }))
+        .count();
+    if existing > 0 {
+        println!(" {} {} tasks already existed", "✓".green().bold(), existing);
+    }
+
+    for (index, outcome) in outcomes.iter().enumerate() {
+        if let BatchOutcome::Conflict { existing } = outcome {
+            println!(
+                " {} Task {} was rejected, as task {} holds its unique key",
+                "⚠".yellow().bold(),
+                index + 1,
+                existing.to_string().bright_black().italic()
+            );
+        }
+    }
 
     Ok(())
 }
@@ -75,9 +229,49 @@ pub struct ListArgs {
         long,
         required = false,
         default_value_t = 50,
-        help = "Maximum number tasks to list"
+        help = "Maximum number tasks to list per page"
     )]
     limit: u32,
+    #[clap(
+        long,
+        default_value = "",
+        help = "Comma-separated key=value labels to filter by"
+    )]
+    label: Labels,
+    #[clap(long, required = false, help = "Worker running the tasks to filter by")]
+    assignee: Option<WorkerName>,
+    #[clap(
+        long,
+        required = false,
+        help = "Smallest priority value of tasks to list"
+    )]
+    min_priority: Option<TaskPriority>,
+    #[clap(
+        long,
+        required = false,
+        help = "Largest priority value of tasks to list"
+    )]
+    max_priority: Option<TaskPriority>,
+    #[clap(
+        long,
+        required = false,
+        help = "Only list tasks created after this RFC 3339 timestamp"
+    )]
+    created_after: Option<DateTime<Utc>>,
+    #[clap(
+        long,
+        required = false,
+        help = "Only list tasks created before this RFC 3339 timestamp"
+    )]
+    created_before: Option<DateTime<Utc>>,
+    #[clap(
+        long,
+        default_value = "state",
+        help = "Order to list tasks in, other than by state only together with --state"
+    )]
+    sort: TaskSort,
+    #[clap(long, help = "Fetch all pages instead of only the first one")]
+    all: bool,
 }
 
 pub async fn list_command(args: ListArgs, client: Client) -> anyhow::Result<()> {
@@ -85,21 +279,43 @@ pub async fn list_command(args: ListArgs, client: Client) -> anyhow::Result<()>
         util::reexec_with_watch(args.watch_args.watch_interval)?;
     }
 
-    let mut tasks = client.list(args.state, Some(args.limit)).await?;
+    let mut query = ListTasks {
+        state: args.state,
+        limit: Some(args.limit),
+        label: (!args.label.is_empty()).then(|| args.label.to_string()),
+        assignee: args.assignee,
+        min_priority: args.min_priority,
+        max_priority: args.max_priority,
+        created_after: args.created_after,
+        created_before: args.created_before,
+        sort: args.sort,
+        cursor: None,
+    };
+
+    let (page, queue_status) = futures::try_join!(client.list(&query), client.queue_status())?;
 
-    if tasks.len() == args.limit as usize {
+    if queue_status.paused {
         println!(
-            " {} Number of tasks is truncated to limit of {}",
-            "⚠".yellow().bold(),
-            args.limit
+            " {} Queue is paused, no new tasks will be started",
+            "⚠".yellow().bold()
         );
     }
 
-    tasks.sort_by(|a, b| match (a.key.state, b.key.state) {
-        (TaskState::Queued, TaskState::Running) => Ordering::Greater,
-        (TaskState::Running, TaskState::Queued) => Ordering::Less,
-        _ => a.value.cmp(&b.value),
-    });
+    let mut tasks = page.tasks;
+    query.cursor = page.cursor;
+
+    while args.all && query.cursor.is_some() {
+        let page = client.list(&query).await?;
+        tasks.extend(page.tasks);
+        query.cursor = page.cursor;
+    }
+
+    if query.cursor.is_some() {
+        println!(
+            " {} More tasks may match, use --all to list them all",
+            "⚠".yellow().bold()
+        );
+    }
 
     table::print_tasks(tasks);
     Ok(())
@@ -127,6 +343,132 @@ pub async fn show_command(args: ShowArgs, client: Client) -> anyhow::Result<()>
     Ok(())
 }
 
+#[derive(Debug, Args)]
+pub struct LogsArgs {
+    #[clap(required = true, help = "Task to show the output of")]
+    id: Uuid,
+    #[clap(short, long, help = "Keep printing output until the task is done")]
+    follow: bool,
+}
+
+pub async fn logs_command(args: LogsArgs, client: Client) -> anyhow::Result<()> {
+    let mut after = None;
+
+    loop {
+        let logs = client.logs(args.id, after, args.follow).await?;
+
+        for chunk in logs.chunks {
+            match chunk.stream {
+                LogStream::Stdout => print!("{}", chunk.data),
+                LogStream::Stderr => eprint!("{}", chunk.data),
+            }
+            after = Some(chunk.seq);
+        }
+        io::stdout().flush()?;
+
+        if !args.follow || logs.done {
+            return Ok(());
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ResultArgs {
+    #[clap(required = true, help = "Task to show the result of")]
+    id: Uuid,
+}
+
+pub async fn result_command(args: ResultArgs, client: Client) -> anyhow::Result<()> {
+    let result = client.result(args.id).await?;
+
+    match result.outcome {
+        Some(TaskOutcome::HandlerFailed(error)) => bail!("Task {} failed: {error}", result.id),
+        Some(outcome) => bail!("Task {} failed: {outcome}", result.id),
+        None => {}
+    }
+
+    match result.result {
+        Some(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+        None => println!("null"),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct ReprioritizeArgs {
+    #[clap(required = true, help = "Task to reprioritize")]
+    id: Uuid,
+    #[clap(required = true, help = "New task priority, 0 = highest priority")]
+    priority: TaskPriority,
+}
+
+pub async fn reprioritize_command(args: ReprioritizeArgs, client: Client) -> anyhow::Result<()> {
+    let task = client.reprioritize(args.id, args.priority).await?;
+
+    println!(
+        "{} Changed priority of task {} to {}",
+        "✓".green().bold(),
+        task.key.id.to_string().bright_black().italic(),
+        task.value.priority
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct PauseArgs {
+    #[clap(required = true, help = "Tasks to pause")]
+    ids: Vec<Uuid>,
+}
+
+pub async fn pause_command(args: PauseArgs, client: Client) -> anyhow::Result<()> {
+    let task_results =
+        futures::future::join_all(args.ids.compact().into_iter().map(|id| client.pause(id))).await;
+
+    for result in task_results {
+        match result {
+            Ok(task) if task.key.state == TaskState::Running => println!(
+                "{} Requested pause of running task {}",
+                "✓".green().bold(),
+                task.key.id.to_string().bright_black().italic()
+            ),
+            Ok(task) => println!(
+                "{} Paused task {}",
+                "✓".green().bold(),
+                task.key.id.to_string().bright_black().italic()
+            ),
+            Err(err) => println!("{} {}", "✖".red().bold(), err),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct ResumeArgs {
+    #[clap(required = true, help = "Tasks to resume")]
+    ids: Vec<Uuid>,
+}
+
+pub async fn resume_command(args: ResumeArgs, client: Client) -> anyhow::Result<()> {
+    let task_results =
+        futures::future::join_all(args.ids.compact().into_iter().map(|id| client.resume(id))).await;
+
+    for result in task_results {
+        match result {
+            Ok(task) => println!(
+                "{} Resumed task {}",
+                "✓".green().bold(),
+                task.key.id.to_string().bright_black().italic()
+            ),
+            Err(err) => println!("{} {}", "✖".red().bold(), err),
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Args)]
 pub struct CancelArgs {
     #[clap(required = true, help = "Tasks to cancel")]
@@ -150,3 +492,238 @@ pub async fn cancel_command(args: CancelArgs, client: Client) -> anyhow::Result<
 
     Ok(())
 }
+
+#[derive(Debug, Args)]
+pub struct QueueArgs {
+    #[command(subcommand)]
+    command: QueueCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum QueueCommand {
+    /// Show whether the queue is paused
+    Status,
+    /// Stop all workers from starting new tasks
+    Pause,
+    /// Let workers start new tasks again
+    Resume,
+}
+
+pub async fn queue_command(args: QueueArgs, client: Client) -> anyhow::Result<()> {
+    let status = match args.command {
+        QueueCommand::Status => client.queue_status().await?,
+        QueueCommand::Pause => client.pause_queue().await?,
+        QueueCommand::Resume => client.resume_queue().await?,
+    };
+
+    if status.paused {
+        println!("{} Queue is paused", "⚠".yellow().bold());
+    } else {
+        println!("{} Queue is running", "✓".green().bold());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[clap(flatten)]
+    watch_args: WatchArgs,
+}
+
+pub async fn stats_command(args: StatsArgs, client: Client) -> anyhow::Result<()> {
+    if args.watch_args.watch {
+        util::reexec_with_watch(args.watch_args.watch_interval)?;
+    }
+
+    let stats = client.queue_stats().await?;
+
+    if stats.paused {
+        println!("{} Queue is paused", "⚠".yellow().bold());
+    } else {
+        println!("{} Queue is running", "✓".green().bold());
+    }
+
+    if let Some(waiting) = stats.oldest_queued {
+        let waiting = Duration::from_secs(waiting.as_secs());
+        println!(
+            "  Oldest queued task has been waiting {}",
+            humantime::format_duration(waiting)
+        );
+    }
+
+    table::print_state_counts(stats.states);
+    table::print_priority_counts(stats.priorities);
+    table::print_throughput(stats.throughput);
+
+    if !stats.groups.is_empty() {
+        table::print_concurrency_limits(stats.groups);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct ConcurrencyArgs {
+    #[command(subcommand)]
+    command: ConcurrencyCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConcurrencyCommand {
+    /// List concurrency limits and running tasks per group
+    List,
+    /// Set the maximum number of running tasks of a group
+    Set {
+        #[clap(help = "Concurrency group")]
+        group: ConcurrencyGroup,
+        #[clap(help = "Maximum number of running tasks")]
+        limit: u32,
+    },
+    /// Remove the concurrency limit of a group
+    Unset {
+        #[clap(help = "Concurrency group")]
+        group: ConcurrencyGroup,
+    },
+}
+
+pub async fn concurrency_command(args: ConcurrencyArgs, client: Client) -> anyhow::Result<()> {
+    let limit = match args.command {
+        ConcurrencyCommand::List => {
+            table::print_concurrency_limits(client.list_concurrency_limits().await?);
+            return Ok(());
+        }
+        ConcurrencyCommand::Set { group, limit } => {
+            client.set_concurrency_limit(&group, limit).await?
+        }
+        ConcurrencyCommand::Unset { group } => client.remove_concurrency_limit(&group).await?,
+    };
+
+    match limit.limit {
+        Some(value) => println!(
+            "{} Limited group {} to {} running tasks",
+            "✓".green().bold(),
+            limit.group,
+            value
+        ),
+        None => println!(
+            "{} Removed limit of group {}",
+            "✓".green().bold(),
+            limit.group
+        ),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct RateLimitArgs {
+    #[command(subcommand)]
+    command: RateLimitCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum RateLimitCommand {
+    /// List rate limits of task starts per group
+    List,
+    /// Limit the rate at which tasks of a group are started
+    Set {
+        #[clap(help = "Concurrency group")]
+        group: ConcurrencyGroup,
+        #[clap(help = "Maximum number of task starts per minute")]
+        per_minute: u32,
+        #[clap(
+            long,
+            help = "Number of tasks that may be started at once [default: 1]"
+        )]
+        burst: Option<u32>,
+    },
+    /// Remove the rate limit of a group
+    Unset {
+        #[clap(help = "Concurrency group")]
+        group: ConcurrencyGroup,
+    },
+}
+
+pub async fn rate_limit_command(args: RateLimitArgs, client: Client) -> anyhow::Result<()> {
+    let limit = match args.command {
+        RateLimitCommand::List => {
+            table::print_rate_limits(client.list_rate_limits().await?);
+            return Ok(());
+        }
+        RateLimitCommand::Set {
+            group,
+            per_minute,
+            burst,
+        } => {
+            client
+                .set_rate_limit(&group, SetRateLimit { per_minute, burst })
+                .await?
+        }
+        RateLimitCommand::Unset { group } => client.remove_rate_limit(&group).await?,
+    };
+
+    match limit.config {
+        Some(config) => println!(
+            "{} Limited group {} to {} task starts per minute",
+            "✓".green().bold(),
+            limit.group,
+            config.per_minute
+        ),
+        None => println!(
+            "{} Removed rate limit of group {}",
+            "✓".green().bold(),
+            limit.group
+        ),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Args)]
+pub struct NodeArgs {
+    #[command(subcommand)]
+    command: NodeCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum NodeCommand {
+    /// Show the capacity of the node and its task acquisitions
+    Status,
+    /// Grow or shrink the worker pool of the node
+    Scale {
+        #[clap(help = "Number of workers")]
+        workers: u32,
+    },
+}
+
+pub async fn node_command(args: NodeArgs, client: Client) -> anyhow::Result<()> {
+    match args.command {
+        NodeCommand::Status => {
+            let status = client.node_status().await?;
+            println!(
+                "{} {} of {} workers idle",
+                "✓".green().bold(),
+                status.capacity.available.cpu,
+                status.capacity.total.cpu
+            );
+            println!(
+                "  {} acquired, {} stolen, {} batch conflicts",
+                status.acquisitions.acquired,
+                status.acquisitions.stolen,
+                status.acquisitions.batch_conflicts
+            );
+        }
+        NodeCommand::Scale { workers } => {
+            let capacity = client.scale_workers(workers).await?;
+            println!(
+                "{} Scaled node to {} workers, {} idle",
+                "✓".green().bold(),
+                capacity.total.cpu,
+                capacity.available.cpu
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -29,13 +29,33 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Submit a new task
-    Submit(SubmitArgs),
+    Submit(Box<SubmitArgs>),
     /// List tasks
     List(ListArgs),
     /// Show specific tasks
     Show(ShowArgs),
+    /// Show the output of a task
+    Logs(LogsArgs),
+    /// Show the result of a finished task
+    Result(ResultArgs),
+    /// Change the priority of a queued task
+    Reprioritize(ReprioritizeArgs),
+    /// Pause tasks
+    Pause(PauseArgs),
+    /// Resume paused tasks
+    Resume(ResumeArgs),
     /// Cancel tasks
     Cancel(CancelArgs),
+    /// Pause or resume the whole queue
+    Queue(QueueArgs),
+    /// Show statistics of the queue
+    Stats(StatsArgs),
+    /// Manage concurrency limits of task groups
+    Concurrency(ConcurrencyArgs),
+    /// Manage rate limits of task starts per group
+    RateLimit(RateLimitArgs),
+    /// Manage the node the client is connected to
+    Node(NodeArgs),
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -44,10 +64,20 @@ async fn main() -> anyhow::Result<()> {
     let basti = Client::new(cli.cluster)?;
 
     let result = match cli.command {
-        Command::Submit(args) => submit_command(args, basti).await,
+        Command::Submit(args) => submit_command(*args, basti).await,
         Command::List(args) => list_command(args, basti).await,
         Command::Show(args) => show_command(args, basti).await,
+        Command::Logs(args) => logs_command(args, basti).await,
+        Command::Result(args) => result_command(args, basti).await,
+        Command::Reprioritize(args) => reprioritize_command(args, basti).await,
+        Command::Pause(args) => pause_command(args, basti).await,
+        Command::Resume(args) => resume_command(args, basti).await,
         Command::Cancel(args) => cancel_command(args, basti).await,
+        Command::Queue(args) => queue_command(args, basti).await,
+        Command::Stats(args) => stats_command(args, basti).await,
+        Command::Concurrency(args) => concurrency_command(args, basti).await,
+        Command::RateLimit(args) => rate_limit_command(args, basti).await,
+        Command::Node(args) => node_command(args, basti).await,
     };
 
     if let Err(err) = result {
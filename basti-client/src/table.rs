@@ -7,7 +7,9 @@ use tabled::{
     },
 };
 
-use basti_types::{Task, TaskState, WorkerName};
+use basti_types::{
+    ConcurrencyLimit, PriorityCount, RateLimit, StateCount, Task, TaskState, Throughput, WorkerName,
+};
 
 const PROGRESS_BAR_LENGTH: usize = 16;
 
@@ -34,17 +36,24 @@ pub fn print_tasks(tasks: Vec<Task>) {
             task.value.remaining
         };
 
-        let progress = if task.value.duration.as_secs() == 0 {
-            0
-        } else {
-            (((task.value.duration - optimistic_remaining).as_secs_f32()
-                / task.value.duration.as_secs_f32())
-                * (PROGRESS_BAR_LENGTH) as f32) as usize
+        // Progress reported by the task's handler takes precedence over the
+        // one estimated from its duration.
+        let progress = match task.value.progress {
+            Some(fraction) => (fraction.clamp(0.0, 1.0) * PROGRESS_BAR_LENGTH as f32) as usize,
+            None if task.value.duration.as_secs() == 0 => 0,
+            None => {
+                (((task.value.duration - optimistic_remaining).as_secs_f32()
+                    / task.value.duration.as_secs_f32())
+                    * (PROGRESS_BAR_LENGTH) as f32) as usize
+            }
         };
 
         builder.push_record([
             task.key.id.to_string(),
-            task.key.state.to_string(),
+            match task.value.outcome {
+                Some(outcome) => format!("{} ({outcome})", task.key.state),
+                None => task.key.state.to_string(),
+            },
             task.value.priority.to_string(),
             task.value
                 .assignee
@@ -74,3 +83,96 @@ pub fn print_tasks(tasks: Vec<Task>) {
         .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
     println!("{table}");
 }
+
+pub fn print_concurrency_limits(limits: Vec<ConcurrencyLimit>) {
+    let mut builder = Builder::new();
+    builder.push_record(["Group", "Running", "Limit"]);
+
+    for limit in limits {
+        builder.push_record([
+            limit.group.to_string(),
+            limit.running.to_string(),
+            limit
+                .limit
+                .map_or_else(|| "none".into(), |limit| limit.to_string()),
+        ]);
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
+    println!("{table}");
+}
+
+pub fn print_rate_limits(limits: Vec<RateLimit>) {
+    let mut builder = Builder::new();
+    builder.push_record(["Group", "Per Minute", "Burst"]);
+
+    for limit in limits {
+        let Some(config) = limit.config else {
+            continue;
+        };
+
+        builder.push_record([
+            limit.group.to_string(),
+            config.per_minute.to_string(),
+            config.burst.to_string(),
+        ]);
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
+    println!("{table}");
+}
+
+pub fn print_state_counts(counts: Vec<StateCount>) {
+    let mut builder = Builder::new();
+    builder.push_record(["State", "Tasks"]);
+
+    for count in counts {
+        builder.push_record([count.state.to_string(), count.tasks.to_string()]);
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
+    println!("{table}");
+}
+
+pub fn print_priority_counts(counts: Vec<PriorityCount>) {
+    let mut builder = Builder::new();
+    builder.push_record(["Priority", "Queued"]);
+
+    for count in counts {
+        builder.push_record([count.priority.to_string(), count.tasks.to_string()]);
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
+    println!("{table}");
+}
+
+pub fn print_throughput(throughput: Vec<Throughput>) {
+    let mut builder = Builder::new();
+    builder.push_record(["Window", "Finished", "Failed"]);
+
+    for window in throughput {
+        builder.push_record([
+            humantime::format_duration(window.window).to_string(),
+            window.finished.to_string(),
+            window.failed.to_string(),
+        ]);
+    }
+
+    let mut table = builder.build();
+    table
+        .with(Style::modern_rounded())
+        .modify(Rows::first(), Color::FG_WHITE | Color::BOLD);
+    println!("{table}");
+}
@@ -1,38 +1,170 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use axum::{
-    extract::{Json, Path, Query, State},
-    http::StatusCode,
+    extract::{FromRef, Json, Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post, put},
     Router,
 };
-use etcd_client::KvClient;
+use metrics::counter;
+use metrics_exporter_prometheus::PrometheusHandle;
 use serde::Deserialize;
+use tokio::time::{sleep, Instant};
 use uuid::Uuid;
 
-use basti_types::{CreateTask, Task, TaskState};
+use basti_types::{
+    BatchOutcome, ConcurrencyGroup, ConcurrencyLimit, CreateTask, DedupeKey, Labels, ListTasks,
+    NodeCapacity, NodeStatus, QueueStats, QueueStatus, RateLimit, Resources, ScaleWorkers,
+    SetConcurrencyLimit, SetRateLimit, Task, TaskKey, TaskLogs, TaskPage, TaskResult, TaskSort,
+    TaskState, TaskValue, UniqueKey, UpdateTask,
+};
 
 use crate::{
-    ops::{cancel_task, create_task, find_task, list_tasks},
-    shutdown_signal,
+    namespace::NamespacedKvClient,
+    ops::{
+        cancel_task, count_tasks, create_task, create_tasks, find_task, list_concurrency_limits,
+        list_logs, list_rate_limits, list_tasks, list_tasks_after, pause_task, queue_stats,
+        queue_status, reprioritize_task, request_pause, requeue_task, set_concurrency_limit,
+        set_queue_paused, set_rate_limit, CreateOutcome,
+    },
+    shutdown_signal, telemetry,
+    worker::{AcquisitionCounters, Capacity, WorkerConfig},
 };
 
-pub async fn run(addr: SocketAddr, client: KvClient) -> anyhow::Result<()> {
+#[derive(Clone)]
+struct AppState {
+    client: NamespacedKvClient,
+    capacity: Arc<Capacity>,
+    counters: Arc<AcquisitionCounters>,
+    config: WorkerConfig,
+    metrics: PrometheusHandle,
+    draining: Draining,
+}
+
+/// Set once the node is shutting down, so load balancers and clients stop
+/// sending it requests before its API goes away.
+#[derive(Clone, Default)]
+struct Draining(Arc<AtomicBool>);
+
+impl FromRef<AppState> for NamespacedKvClient {
+    fn from_ref(state: &AppState) -> Self {
+        state.client.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Capacity> {
+    fn from_ref(state: &AppState) -> Self {
+        state.capacity.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<AcquisitionCounters> {
+    fn from_ref(state: &AppState) -> Self {
+        state.counters.clone()
+    }
+}
+
+impl FromRef<AppState> for PrometheusHandle {
+    fn from_ref(state: &AppState) -> Self {
+        state.metrics.clone()
+    }
+}
+
+impl FromRef<AppState> for Draining {
+    fn from_ref(state: &AppState) -> Self {
+        state.draining.clone()
+    }
+}
+
+impl FromRef<AppState> for WorkerConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config
+    }
+}
+
+pub async fn run(
+    addr: SocketAddr,
+    client: NamespacedKvClient,
+    capacity: Arc<Capacity>,
+    counters: Arc<AcquisitionCounters>,
+    config: WorkerConfig,
+    metrics: PrometheusHandle,
+    drain_period: Duration,
+) -> anyhow::Result<()> {
+    let draining = Draining::default();
+
     let app = Router::new()
         .route("/api/tasks", post(create_task_endpoint))
         .route("/api/tasks", get(list_tasks_endpoint))
+        .route("/api/tasks/batch", post(create_tasks_endpoint))
         .route("/api/tasks/:id", get(find_task_endpoint))
+        .route("/api/tasks/:id", patch(update_task_endpoint))
         .route("/api/tasks/:id", delete(cancel_task_endpoint))
-        .with_state(client);
+        .route("/api/tasks/:id/pause", post(pause_task_endpoint))
+        .route("/api/tasks/:id/resume", post(resume_task_endpoint))
+        .route("/api/tasks/:id/logs", get(task_logs_endpoint))
+        .route("/api/tasks/:id/result", get(task_result_endpoint))
+        .route("/api/queue", get(queue_status_endpoint))
+        .route("/api/queue/pause", post(pause_queue_endpoint))
+        .route("/api/queue/resume", post(resume_queue_endpoint))
+        .route("/api/stats", get(queue_stats_endpoint))
+        .route("/api/concurrency", get(list_concurrency_limits_endpoint))
+        .route(
+            "/api/concurrency/:group",
+            put(set_concurrency_limit_endpoint),
+        )
+        .route(
+            "/api/concurrency/:group",
+            delete(remove_concurrency_limit_endpoint),
+        )
+        .route("/api/rate-limits", get(list_rate_limits_endpoint))
+        .route("/api/rate-limits/:group", put(set_rate_limit_endpoint))
+        .route(
+            "/api/rate-limits/:group",
+            delete(remove_rate_limit_endpoint),
+        )
+        .route("/api/node", get(node_status_endpoint))
+        .route("/api/node/workers", put(scale_workers_endpoint))
+        .route("/metrics", get(metrics_endpoint))
+        .route("/healthz", get(health_endpoint))
+        .route("/readyz", get(readiness_endpoint))
+        .with_state(AppState {
+            client,
+            capacity,
+            counters,
+            config,
+            metrics: metrics.clone(),
+            draining: draining.clone(),
+        });
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
     tracing::info!("Listening at http://{addr}");
 
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .map_err(anyhow::Error::from)
+    // The node keeps serving requests while failing readiness checks for the
+    // drain period, giving load balancers time to take it out of rotation.
+    let shutdown = async move {
+        shutdown_signal().await;
+        draining.0.store(true, Ordering::Relaxed);
+        tracing::info!("Draining for {}", humantime::format_duration(drain_period));
+        sleep(drain_period).await;
+    };
+
+    let upkeep = tokio::spawn(telemetry::run_upkeep(metrics));
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await;
+    upkeep.abort();
+
+    result.map_err(anyhow::Error::from)
 }
 
 #[derive(Debug)]
@@ -51,36 +183,344 @@ impl IntoResponse for Error {
     }
 }
 
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const INVALID_TIMEOUTS: &str =
+    "Heartbeat interval must be non-zero and shorter than the stale timeout";
+const NO_RESOURCES: &str = "Tasks need to request some CPU or memory";
+const PAYLOAD_TOO_LARGE: &str = "Task payload is too large";
+
+fn valid_payload_size(payload: &CreateTask) -> anyhow::Result<bool> {
+    Ok(match &payload.payload {
+        Some(value) => serde_json::to_vec(value)?.len() <= TaskValue::MAX_PAYLOAD_SIZE,
+        None => true,
+    })
+}
+
+fn valid_reference_keys(payload: &CreateTask) -> bool {
+    let valid_length = |key: &String, max_length| (1..=max_length).contains(&key.len());
+
+    payload
+        .dedupe_key
+        .as_ref()
+        .is_none_or(|key| valid_length(key, DedupeKey::MAX_LENGTH))
+        && payload
+            .unique_key
+            .as_ref()
+            .is_none_or(|key| valid_length(key, UniqueKey::MAX_LENGTH))
+}
+
 #[tracing::instrument(skip(client), err(Debug))]
 pub async fn create_task_endpoint(
-    State(mut client): State<KvClient>,
-    Json(payload): Json<CreateTask>,
-) -> Result<(StatusCode, Json<Task>)> {
-    let task = create_task(&mut client, payload.duration, payload.priority).await?;
-    Ok((StatusCode::CREATED, Json(task)))
+    State(mut client): State<NamespacedKvClient>,
+    State(config): State<WorkerConfig>,
+    headers: HeaderMap,
+    Json(mut payload): Json<CreateTask>,
+) -> Result<Response> {
+    if let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) {
+        let Ok(key) = key.to_str() else {
+            return Ok((StatusCode::BAD_REQUEST, "Invalid idempotency key").into_response());
+        };
+        payload.dedupe_key = Some(key.to_string());
+    }
+
+    if !valid_reference_keys(&payload) {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid dedupe or unique key").into_response());
+    }
+
+    if !config.fill_task_timeouts(&mut payload) {
+        return Ok((StatusCode::BAD_REQUEST, INVALID_TIMEOUTS).into_response());
+    }
+
+    // Tasks without resources would always fit, letting a single node take
+    // all of them.
+    if payload.resources == Resources::ZERO {
+        return Ok((StatusCode::BAD_REQUEST, NO_RESOURCES).into_response());
+    }
+
+    if !valid_payload_size(&payload)? {
+        return Ok((StatusCode::BAD_REQUEST, PAYLOAD_TOO_LARGE).into_response());
+    }
+
+    Ok(match create_task(&mut client, payload).await? {
+        CreateOutcome::Created(task) => {
+            counter!(telemetry::TASKS_CREATED).increment(1);
+            (StatusCode::CREATED, Json(task)).into_response()
+        }
+        CreateOutcome::Existing(task) => (StatusCode::OK, Json(task)).into_response(),
+        CreateOutcome::Conflict(task) => (
+            StatusCode::CONFLICT,
+            format!("Unique key is held by task {}", task.key.id),
+        )
+            .into_response(),
+    })
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ListTasksParams {
-    state: Option<TaskState>,
-    limit: Option<i64>,
+#[tracing::instrument(skip(client, payload), err(Debug))]
+pub async fn create_tasks_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    State(config): State<WorkerConfig>,
+    Json(mut payload): Json<Vec<CreateTask>>,
+) -> Result<Response> {
+    if !payload.iter().all(valid_reference_keys) {
+        return Ok((StatusCode::BAD_REQUEST, "Invalid dedupe or unique key").into_response());
+    }
+
+    if !payload
+        .iter_mut()
+        .all(|task| config.fill_task_timeouts(task))
+    {
+        return Ok((StatusCode::BAD_REQUEST, INVALID_TIMEOUTS).into_response());
+    }
+
+    if payload.iter().any(|task| task.resources == Resources::ZERO) {
+        return Ok((StatusCode::BAD_REQUEST, NO_RESOURCES).into_response());
+    }
+
+    for task in &payload {
+        if !valid_payload_size(task)? {
+            return Ok((StatusCode::BAD_REQUEST, PAYLOAD_TOO_LARGE).into_response());
+        }
+    }
+
+    // Outcomes are in the order the tasks were submitted in.
+    let outcomes = create_tasks(&mut client, payload)
+        .await?
+        .into_iter()
+        .map(|outcome| match outcome {
+            CreateOutcome::Created(task) => {
+                counter!(telemetry::TASKS_CREATED).increment(1);
+                BatchOutcome::Created { task }
+            }
+            CreateOutcome::Existing(task) => BatchOutcome::Existing { task },
+            CreateOutcome::Conflict(task) => BatchOutcome::Conflict {
+                existing: task.key.id,
+            },
+        })
+        .collect::<Vec<_>>();
+    Ok((StatusCode::OK, Json(outcomes)).into_response())
+}
+
+/// Number of tasks listed if the query doesn't specify a limit.
+const DEFAULT_LIST_LIMIT: u32 = 50;
+/// Number of tasks fetched at once while filtering tasks sorted by state.
+const LIST_BATCH_SIZE: i64 = 100;
+/// Most tasks read to serve a page, however few of them match the query.
+const LIST_SCAN_LIMIT: usize = 1000;
+/// Order in which states are listed when sorting by state.
+const LIST_STATE_ORDER: [TaskState; 5] = [
+    TaskState::Running,
+    TaskState::Queued,
+    TaskState::Paused,
+    TaskState::Failed,
+    TaskState::Finished,
+];
+
+/// Position of the last task of a page, after which the next page starts.
+/// Handed out to clients as an opaque string.
+struct ListCursor {
+    sort: TaskSort,
+    value: i64,
+    key: TaskKey,
+}
+
+impl ListCursor {
+    fn new(sort: TaskSort, task: &Task) -> Self {
+        Self {
+            sort,
+            value: sort_value(sort, task),
+            key: task.key,
+        }
+    }
+
+    fn encode(&self) -> String {
+        let key = hex::encode(Vec::from(&self.key));
+        format!("{}.{}.{key}", self.sort, self.value)
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let mut parts = cursor.splitn(3, '.');
+        let sort = TaskSort::from_str(parts.next()?).ok()?;
+        let value = parts.next()?.parse().ok()?;
+        let key = hex::decode(parts.next()?).ok()?;
+
+        Some(Self {
+            sort,
+            value,
+            key: TaskKey::try_from(key.as_slice()).ok()?,
+        })
+    }
+}
+
+/// Value tasks are ordered by, ascending, before ties are broken by ID.
+fn sort_value(sort: TaskSort, task: &Task) -> i64 {
+    match sort {
+        TaskSort::State => 0,
+        TaskSort::Priority => i64::from(task.value.priority.0),
+        TaskSort::CreatedAt => task.value.created_at.timestamp_micros(),
+        TaskSort::UpdatedAt => task.value.updated_at.timestamp_micros(),
+    }
+}
+
+fn matches_query(query: &ListTasks, selector: &Labels, task: &Task) -> bool {
+    let value = &task.value;
+    let assignee = query.assignee.as_ref();
+
+    assignee.is_none_or(|assignee| value.assignee.as_ref() == Some(assignee))
+        && query.min_priority.is_none_or(|min| value.priority >= min)
+        && query.max_priority.is_none_or(|max| value.priority <= max)
+        && query
+            .created_after
+            .is_none_or(|time| value.created_at > time)
+        && query
+            .created_before
+            .is_none_or(|time| value.created_at < time)
+        && value.labels.matches(selector)
 }
 
 #[tracing::instrument(skip(client), err(Debug))]
 pub async fn list_tasks_endpoint(
-    State(mut client): State<KvClient>,
-    Query(params): Query<ListTasksParams>,
-) -> Result<(StatusCode, Json<Vec<Task>>)> {
-    let tasks = list_tasks(&mut client, params.state, params.limit.unwrap_or(50)).await?;
-    Ok((
-        StatusCode::OK,
-        Json(tasks.into_iter().map(|(task, _)| task).collect()),
-    ))
+    State(mut client): State<NamespacedKvClient>,
+    Query(query): Query<ListTasks>,
+) -> Result<Response> {
+    let limit = match query.limit.unwrap_or(DEFAULT_LIST_LIMIT) {
+        0 => usize::MAX,
+        limit => limit as usize,
+    };
+
+    let selector = match &query.label {
+        None => Labels::default(),
+        Some(label) => match Labels::from_str(label) {
+            Ok(selector) => selector,
+            Err(_) => {
+                return Ok((StatusCode::BAD_REQUEST, "Invalid label selector").into_response())
+            }
+        },
+    };
+
+    // Only tasks by state are listed in key order, so other orders are limited
+    // to the tasks of one state, which are all read and sorted for each page.
+    if query.sort != TaskSort::State && query.state.is_none() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Sorting by other than state requires a state filter",
+        )
+            .into_response());
+    }
+
+    let cursor = match &query.cursor {
+        None => None,
+        Some(cursor) => match ListCursor::decode(cursor) {
+            Some(cursor) if cursor.sort == query.sort => Some(cursor),
+            _ => return Ok((StatusCode::BAD_REQUEST, "Invalid cursor").into_response()),
+        },
+    };
+
+    let (tasks, next) = if query.sort == TaskSort::State {
+        let states = match query.state {
+            None => LIST_STATE_ORDER.to_vec(),
+            Some(state) => vec![state],
+        };
+
+        // Pages continue in the state of the cursor, right after its key.
+        let (skip, mut after) = match cursor {
+            None => (0, None),
+            Some(cursor) => match states.iter().position(|state| *state == cursor.key.state) {
+                Some(skip) => (skip, Some(Vec::from(&cursor.key))),
+                None => return Ok((StatusCode::BAD_REQUEST, "Invalid cursor").into_response()),
+            },
+        };
+
+        // Pages end once they are full or enough tasks were scanned, so
+        // selective filters don't read the whole keyspace in one request.
+        let mut tasks = Vec::new();
+        let mut scanned = 0;
+        let mut next = None;
+        'states: for state in states.into_iter().skip(skip) {
+            loop {
+                let batch =
+                    list_tasks_after(&mut client, state, after.as_deref(), LIST_BATCH_SIZE).await?;
+                let exhausted = batch.len() < LIST_BATCH_SIZE as usize;
+                after = batch.last().map(|(task, _)| Vec::from(&task.key));
+
+                for (task, _) in batch {
+                    scanned += 1;
+                    let key = task.key;
+                    if matches_query(&query, &selector, &task) {
+                        tasks.push(task);
+                    }
+
+                    if tasks.len() == limit || scanned == LIST_SCAN_LIMIT {
+                        next = Some(ListCursor {
+                            sort: TaskSort::State,
+                            value: 0,
+                            key,
+                        });
+                        break 'states;
+                    }
+                }
+
+                if exhausted {
+                    break;
+                }
+            }
+
+            after = None;
+        }
+
+        (tasks, next)
+    } else {
+        // Other orders aren't indexed, so all tasks of the state are read and
+        // sorted for each page, which is only done for few enough tasks.
+        let Some(state) = query.state else {
+            unreachable!("checked above")
+        };
+        if count_tasks(&mut client, state).await? > LIST_SCAN_LIMIT as u64 {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                "Too many tasks in this state to sort by other than state",
+            )
+                .into_response());
+        }
+
+        let mut tasks = list_tasks(&mut client, Some(state), 0)
+            .await?
+            .into_iter()
+            .map(|(task, _)| task)
+            .filter(|task| matches_query(&query, &selector, task))
+            .map(|task| ((sort_value(query.sort, &task), task.key.id), task))
+            .collect::<Vec<_>>();
+        tasks.sort_by_key(|(position, _)| *position);
+
+        if let Some(cursor) = cursor {
+            tasks.retain(|(position, _)| *position > (cursor.value, cursor.key.id));
+        }
+
+        let more = tasks.len() > limit;
+        let tasks = tasks
+            .into_iter()
+            .map(|(_, task)| task)
+            .take(limit)
+            .collect::<Vec<_>>();
+        let next = tasks
+            .last()
+            .filter(|_| more)
+            .map(|task| ListCursor::new(query.sort, task));
+        (tasks, next)
+    };
+
+    let mut response = (StatusCode::OK, Json(tasks)).into_response();
+    if let Some(next) = next {
+        response.headers_mut().insert(
+            TaskPage::CURSOR_HEADER,
+            HeaderValue::try_from(next.encode()).map_err(anyhow::Error::from)?,
+        );
+    }
+    Ok(response)
 }
 
 #[tracing::instrument(skip(client), err(Debug))]
 pub async fn find_task_endpoint(
-    State(mut client): State<KvClient>,
+    State(mut client): State<NamespacedKvClient>,
     Path(id): Path<Uuid>,
 ) -> Result<Response> {
     Ok(
@@ -91,9 +531,137 @@ pub async fn find_task_endpoint(
     )
 }
 
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn update_task_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateTask>,
+) -> Result<Response> {
+    let Some((task, revision)) = find_task(&mut client, id, &TaskState::VARIANTS).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Task not found").into_response());
+    };
+
+    if task.key.state != TaskState::Queued {
+        return Ok((StatusCode::CONFLICT, "Task is not queued").into_response());
+    }
+
+    Ok(
+        match reprioritize_task(&mut client, task, revision, payload.priority).await? {
+            Some((task, _)) => (StatusCode::OK, Json(task)).into_response(),
+            None => (StatusCode::CONFLICT, "Task was modified concurrently").into_response(),
+        },
+    )
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn pause_task_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(id): Path<Uuid>,
+) -> Result<Response> {
+    let Some((task, revision)) = find_task(&mut client, id, &TaskState::VARIANTS).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Task not found").into_response());
+    };
+
+    Ok(match task.key.state {
+        TaskState::Paused => (StatusCode::OK, Json(task)).into_response(),
+        TaskState::Failed | TaskState::Finished => {
+            (StatusCode::CONFLICT, "Task is done").into_response()
+        }
+        TaskState::Queued => match pause_task(&mut client, task, revision).await? {
+            Some((task, _)) => (StatusCode::OK, Json(task)).into_response(),
+            None => (StatusCode::CONFLICT, "Task was modified concurrently").into_response(),
+        },
+        // Running tasks are paused by their assignee at the next checkpoint.
+        TaskState::Running => {
+            if request_pause(&mut client, id).await? {
+                (StatusCode::ACCEPTED, Json(task)).into_response()
+            } else {
+                (StatusCode::CONFLICT, "Task was modified concurrently").into_response()
+            }
+        }
+    })
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn resume_task_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(id): Path<Uuid>,
+) -> Result<Response> {
+    let Some((task, revision)) = find_task(&mut client, id, &TaskState::VARIANTS).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Task not found").into_response());
+    };
+
+    if task.key.state != TaskState::Paused {
+        return Ok((StatusCode::CONFLICT, "Task is not paused").into_response());
+    }
+
+    Ok(match requeue_task(&mut client, task, revision).await? {
+        Some((task, _)) => (StatusCode::OK, Json(task)).into_response(),
+        None => (StatusCode::CONFLICT, "Task was modified concurrently").into_response(),
+    })
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn task_result_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(id): Path<Uuid>,
+) -> Result<Response> {
+    let Some((task, _)) = find_task(&mut client, id, &TaskState::VARIANTS).await? else {
+        return Ok((StatusCode::NOT_FOUND, "Task not found").into_response());
+    };
+
+    if !task.key.state.is_terminal() {
+        return Ok((StatusCode::CONFLICT, "Task is not done yet").into_response());
+    }
+
+    Ok((StatusCode::OK, Json(TaskResult::from(task))).into_response())
+}
+
+/// How long a follow request waits for new output before returning.
+const LOG_FOLLOW_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize)]
+pub struct TaskLogsParams {
+    after: Option<u32>,
+    #[serde(default)]
+    follow: bool,
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn task_logs_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    State(config): State<WorkerConfig>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<TaskLogsParams>,
+) -> Result<Response> {
+    let started = Instant::now();
+
+    loop {
+        // The task is looked up first, so no output written before it is done
+        // can be missed.
+        let task = find_task(&mut client, id, &TaskState::VARIANTS).await?;
+        let chunks = list_logs(&mut client, id, params.after).await?;
+
+        if task.is_none() && chunks.is_empty() && params.after.is_none() {
+            return Ok((StatusCode::NOT_FOUND, "Task not found").into_response());
+        }
+
+        let done = task.is_none_or(|(task, _)| task.key.state.is_terminal());
+
+        if !params.follow || !chunks.is_empty() || done || started.elapsed() >= LOG_FOLLOW_TIMEOUT {
+            return Ok((StatusCode::OK, Json(TaskLogs { chunks, done })).into_response());
+        }
+
+        // Requests return in time however long the poll interval is, as
+        // clients time out soon after the follow timeout.
+        let until_timeout = LOG_FOLLOW_TIMEOUT.saturating_sub(started.elapsed());
+        sleep(config.poll_interval.min(until_timeout)).await;
+    }
+}
+
 #[tracing::instrument(skip(client), err(Debug))]
 pub async fn cancel_task_endpoint(
-    State(mut client): State<KvClient>,
+    State(mut client): State<NamespacedKvClient>,
     Path(id): Path<Uuid>,
 ) -> Result<Response> {
     Ok(match cancel_task(&mut client, id).await? {
@@ -101,3 +669,212 @@ pub async fn cancel_task_endpoint(
         None => (StatusCode::NOT_FOUND, "Task not found").into_response(),
     })
 }
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn queue_status_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<QueueStatus>)> {
+    let status = queue_status(&mut client).await?;
+    Ok((StatusCode::OK, Json(status)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn queue_stats_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<QueueStats>)> {
+    let stats = queue_stats(&mut client).await?;
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn pause_queue_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<QueueStatus>)> {
+    let status = set_queue_paused(&mut client, true).await?;
+    Ok((StatusCode::OK, Json(status)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn resume_queue_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<QueueStatus>)> {
+    let status = set_queue_paused(&mut client, false).await?;
+    Ok((StatusCode::OK, Json(status)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn list_concurrency_limits_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<Vec<ConcurrencyLimit>>)> {
+    let limits = list_concurrency_limits(&mut client).await?;
+    Ok((StatusCode::OK, Json(limits)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn set_concurrency_limit_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(group): Path<ConcurrencyGroup>,
+    Json(payload): Json<SetConcurrencyLimit>,
+) -> Result<(StatusCode, Json<ConcurrencyLimit>)> {
+    let limit = set_concurrency_limit(&mut client, group, Some(payload.limit)).await?;
+    Ok((StatusCode::OK, Json(limit)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn remove_concurrency_limit_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(group): Path<ConcurrencyGroup>,
+) -> Result<(StatusCode, Json<ConcurrencyLimit>)> {
+    let limit = set_concurrency_limit(&mut client, group, None).await?;
+    Ok((StatusCode::OK, Json(limit)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn list_rate_limits_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+) -> Result<(StatusCode, Json<Vec<RateLimit>>)> {
+    let limits = list_rate_limits(&mut client).await?;
+    Ok((StatusCode::OK, Json(limits)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn set_rate_limit_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(group): Path<ConcurrencyGroup>,
+    Json(payload): Json<SetRateLimit>,
+) -> Result<(StatusCode, Json<RateLimit>)> {
+    let limit = set_rate_limit(&mut client, group, Some(payload.into())).await?;
+    Ok((StatusCode::OK, Json(limit)))
+}
+
+#[tracing::instrument(skip(client), err(Debug))]
+pub async fn remove_rate_limit_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    Path(group): Path<ConcurrencyGroup>,
+) -> Result<(StatusCode, Json<RateLimit>)> {
+    let limit = set_rate_limit(&mut client, group, None).await?;
+    Ok((StatusCode::OK, Json(limit)))
+}
+
+#[tracing::instrument(skip(capacity, counters), err(Debug))]
+pub async fn node_status_endpoint(
+    State(capacity): State<Arc<Capacity>>,
+    State(counters): State<Arc<AcquisitionCounters>>,
+) -> Result<(StatusCode, Json<NodeStatus>)> {
+    let status = NodeStatus {
+        capacity: capacity.get(),
+        acquisitions: counters.get(),
+    };
+    Ok((StatusCode::OK, Json(status)))
+}
+
+#[tracing::instrument(skip(capacity), err(Debug))]
+pub async fn scale_workers_endpoint(
+    State(capacity): State<Arc<Capacity>>,
+    Json(payload): Json<ScaleWorkers>,
+) -> Result<(StatusCode, Json<NodeCapacity>)> {
+    let capacity = capacity.scale(payload.workers);
+    tracing::info!(workers = payload.workers, event = "scaled");
+    Ok((StatusCode::OK, Json(capacity)))
+}
+
+/// Renders the node's metrics in the Prometheus text format. Gauges of the
+/// node and the queue are updated on every scrape.
+#[tracing::instrument(skip_all)]
+pub async fn metrics_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    State(capacity): State<Arc<Capacity>>,
+    State(counters): State<Arc<AcquisitionCounters>>,
+    State(metrics): State<PrometheusHandle>,
+) -> Response {
+    telemetry::record_node_status(NodeStatus {
+        capacity: capacity.get(),
+        acquisitions: counters.get(),
+    });
+
+    // The node's own metrics are still served while etcd is unreachable, with
+    // the queue depths of the last successful scrape.
+    for state in TaskState::VARIANTS {
+        match count_tasks(&mut client, state).await {
+            Ok(tasks) => telemetry::record_queue_depth(state, tasks),
+            Err(err) => {
+                tracing::warn!("failed to count tasks for metrics: {err}");
+                break;
+            }
+        }
+    }
+
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+        .into_response()
+}
+
+/// Reports that the process is alive, regardless of its dependencies.
+pub async fn health_endpoint() -> &'static str {
+    "OK"
+}
+
+/// Reports whether the node can serve requests, i.e. etcd is reachable and
+/// the node isn't shutting down.
+#[tracing::instrument(skip_all)]
+pub async fn readiness_endpoint(
+    State(mut client): State<NamespacedKvClient>,
+    State(draining): State<Draining>,
+) -> Response {
+    if draining.0.load(Ordering::Relaxed) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Node is draining").into_response();
+    }
+
+    match queue_status(&mut client).await {
+        Ok(_) => (StatusCode::OK, "OK").into_response(),
+        Err(err) => {
+            tracing::warn!("etcd is unreachable: {err}");
+            (StatusCode::SERVICE_UNAVAILABLE, "etcd is unreachable").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor() -> ListCursor {
+        ListCursor {
+            sort: TaskSort::CreatedAt,
+            value: -42,
+            key: TaskKey::new(TaskState::Queued, Uuid::from_u128(0x1234)),
+        }
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let decoded = ListCursor::decode(&cursor().encode()).unwrap();
+
+        assert_eq!(decoded.sort, TaskSort::CreatedAt);
+        assert_eq!(decoded.value, -42);
+        assert_eq!(Vec::from(&decoded.key), Vec::from(&cursor().key));
+    }
+
+    #[test]
+    fn cursor_rejects_malformed() {
+        let encoded = cursor().encode();
+        let key = encoded.rsplit('.').next().unwrap();
+
+        for malformed in [
+            String::new(),
+            "created_at".to_string(),
+            "created_at.-42".to_string(),
+            format!("unknown.-42.{key}"),
+            format!("created_at.value.{key}"),
+            format!("created_at.-42.{}", &key[1..]),
+            format!("created_at.-42.{}zz", &key[2..]),
+            format!("created_at.-42.{}", &key[2..]),
+            format!("created_at.-42.{key}.extra"),
+        ] {
+            assert!(ListCursor::decode(&malformed).is_none(), "{malformed}");
+        }
+    }
+}
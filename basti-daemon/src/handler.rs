@@ -0,0 +1,132 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use basti_types::{LogStream, Task};
+
+use crate::ops::ProgressReport;
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = anyhow::Result<serde_json::Value>> + Send>>;
+
+/// Works on tasks, returning their result. A handler reports its progress
+/// through the given reporter; the worker stores what was reported with each
+/// heartbeat, which it sends on its own timer. Its future is dropped when the
+/// task is paused, times out or is taken over by another node.
+pub trait Handler: Send + Sync {
+    fn run(&self, task: Task, reporter: Reporter) -> HandlerFuture;
+}
+
+/// Handle through which a handler reports on the task it works on. Reports
+/// are buffered until the worker's next heartbeat.
+#[derive(Clone, Default)]
+pub struct Reporter(Arc<Mutex<Reports>>);
+
+#[derive(Default)]
+struct Reports {
+    progress: ProgressReport,
+    output: Vec<(LogStream, String)>,
+}
+
+impl Reporter {
+    /// Reports the fraction of the task which is done.
+    pub fn report_progress(&self, fraction: f32) {
+        self.0.lock().unwrap().progress.fraction = Some(fraction);
+    }
+
+    /// Reports the state to resume from if the task is started again,
+    /// replacing the previous checkpoint.
+    pub fn checkpoint(&self, checkpoint: serde_json::Value) {
+        self.0.lock().unwrap().progress.checkpoint = Some(checkpoint);
+    }
+
+    pub fn write(&self, stream: LogStream, data: impl Into<String>) {
+        let data = data.into();
+        let output = &mut self.0.lock().unwrap().output;
+        match output.last_mut() {
+            Some((last, pending)) if *last == stream => pending.push_str(&data),
+            _ => output.push((stream, data)),
+        }
+    }
+
+    /// Takes the progress reported since the last call.
+    pub fn take_progress(&self) -> ProgressReport {
+        std::mem::take(&mut self.0.lock().unwrap().progress)
+    }
+
+    /// Takes the output written since the last call, with consecutive writes
+    /// to the same stream joined.
+    pub fn take_output(&self) -> Vec<(LogStream, String)> {
+        std::mem::take(&mut self.0.lock().unwrap().output)
+    }
+}
+
+/// Time the simulation works between reports.
+const SIMULATION_STEP: Duration = Duration::from_millis(100);
+
+/// Handler which simulates work by sleeping for the duration of a task. It
+/// checkpoints the amount of work done, so a requeued task resumes where it
+/// left off on any node.
+pub struct Simulation;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    worked_ms: u64,
+}
+
+impl Handler for Simulation {
+    fn run(&self, task: Task, reporter: Reporter) -> HandlerFuture {
+        Box::pin(async move {
+            let duration = task.value.duration;
+            let worked = task
+                .value
+                .checkpoint
+                .and_then(|checkpoint| serde_json::from_value::<Checkpoint>(checkpoint).ok())
+                .map_or_else(
+                    || duration.saturating_sub(task.value.remaining),
+                    |checkpoint| Duration::from_millis(checkpoint.worked_ms),
+                );
+            let mut worked = worked.min(duration);
+
+            if worked.is_zero() {
+                reporter.write(LogStream::Stdout, "starting\n");
+            } else {
+                reporter.write(
+                    LogStream::Stdout,
+                    format!(
+                        "resuming at {}.{:03}s\n",
+                        worked.as_secs(),
+                        worked.subsec_millis()
+                    ),
+                );
+            }
+
+            while worked < duration {
+                let work = (duration - worked).min(SIMULATION_STEP);
+                sleep(work).await;
+                worked += work;
+
+                reporter.report_progress(worked.as_secs_f32() / duration.as_secs_f32());
+                reporter.checkpoint(serde_json::to_value(Checkpoint {
+                    worked_ms: u64::try_from(worked.as_millis())?,
+                })?);
+            }
+
+            reporter.write(
+                LogStream::Stdout,
+                format!(
+                    "done after {}.{:03}s\n",
+                    worked.as_secs(),
+                    worked.subsec_millis()
+                ),
+            );
+
+            Ok(serde_json::json!({ "worked_ms": worked.as_millis() as u64 }))
+        })
+    }
+}
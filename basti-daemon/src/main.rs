@@ -1,15 +1,26 @@
 mod api;
+mod handler;
+mod namespace;
 mod ops;
+mod telemetry;
 mod worker;
 
-use std::{net::SocketAddr, num::NonZeroUsize, process::exit, str::FromStr, time::Duration};
+use std::{env, net::SocketAddr, process::exit, str::FromStr, sync::Arc, time::Duration};
 
-use clap::Parser;
+use anyhow::{ensure, Context};
+use clap::{Parser, ValueEnum};
 use etcd_client::{Client, ConnectOptions};
 use tokio::{signal, task::JoinSet};
+use tracing_subscriber::EnvFilter;
 use url::Url;
 
-use basti_types::WorkerName;
+use basti_types::{Labels, Namespace, Resources, WorkerName};
+
+use crate::{
+    handler::Simulation,
+    namespace::NamespacedKvClient,
+    worker::{AcquisitionCounters, Capacity, WorkerConfig},
+};
 
 #[derive(Debug, Parser)]
 struct Cli {
@@ -18,11 +29,19 @@ struct Cli {
 
     #[clap(
         long,
-        env = "BASTID_WORKERS",
-        default_value_t = 1,
-        help = "Number of workers to run"
+        visible_alias = "workers",
+        env = "BASTID_CPU",
+        help = "CPU units available to tasks, i.e. the number of workers [default: 1, or BASTID_WORKERS]"
     )]
-    workers: usize,
+    cpu: Option<u32>,
+
+    #[clap(
+        long,
+        env = "BASTID_MEMORY",
+        default_value_t = 0,
+        help = "Memory units available to tasks"
+    )]
+    memory: u32,
 
     #[clap(
         long,
@@ -48,6 +67,100 @@ struct Cli {
         help = "Comma-separated list of etcd endpoints"
     )]
     etcd: Vec<Url>,
+
+    #[clap(
+        long,
+        env = "BASTID_NAMESPACE",
+        default_value = "",
+        help = "Namespace to store all etcd keys in, under ns/<namespace>/ [default: the root of the keyspace]"
+    )]
+    namespace: Namespace,
+
+    #[clap(
+        long,
+        env = "BASTID_LABELS",
+        default_value = "",
+        help = "Comma-separated key=value labels matched against task selectors"
+    )]
+    labels: Labels,
+
+    #[clap(
+        long,
+        env = "BASTID_HEARTBEAT_INTERVAL",
+        default_value = "5s",
+        value_parser = humantime::parse_duration,
+        help = "Interval at which running tasks report progress"
+    )]
+    heartbeat_interval: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_STALE_TIMEOUT",
+        default_value = "10s",
+        value_parser = humantime::parse_duration,
+        help = "Time without progress after which running tasks are requeued"
+    )]
+    stale_timeout: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_POLL_INTERVAL",
+        default_value = "500ms",
+        value_parser = humantime::parse_duration,
+        help = "Interval at which to poll for work and stale tasks"
+    )]
+    poll_interval: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_ERROR_BACKOFF",
+        default_value = "5s",
+        value_parser = humantime::parse_duration,
+        help = "Time to wait after an error before trying again"
+    )]
+    error_backoff: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_RETENTION",
+        default_value = "7days",
+        value_parser = humantime::parse_duration,
+        help = "Time after which finished and failed tasks are deleted along with their output"
+    )]
+    retention: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_DRAIN_PERIOD",
+        default_value = "2s",
+        value_parser = humantime::parse_duration,
+        help = "Time to keep serving the API while failing readiness checks on shutdown"
+    )]
+    drain_period: Duration,
+
+    #[clap(
+        long,
+        env = "BASTID_LOG_FORMAT",
+        default_value = "text",
+        help = "Format of log output"
+    )]
+    log_format: LogFormat,
+
+    #[clap(
+        long,
+        env = "RUST_LOG",
+        default_value = "info",
+        help = "Log filter, either a level or comma-separated target=level directives"
+    )]
+    log_level: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with event and span fields
+    Json,
 }
 
 fn default_worker_name() -> WorkerName {
@@ -62,7 +175,42 @@ fn default_worker_name() -> WorkerName {
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
-    tracing_subscriber::fmt().with_target(false).init();
+    // Deployments configured before CPU units replaced workers still set the
+    // number of workers through the environment.
+    let cpu = match (args.cpu, env::var("BASTID_WORKERS")) {
+        (Some(cpu), _) => cpu,
+        (None, Ok(workers)) => workers
+            .parse()
+            .context("BASTID_WORKERS must be a number of workers")?,
+        (None, Err(_)) => 1,
+    };
+
+    let config = WorkerConfig {
+        heartbeat_interval: args.heartbeat_interval,
+        stale_timeout: args.stale_timeout,
+        poll_interval: args.poll_interval,
+        error_backoff: args.error_backoff,
+        retention: args.retention,
+    };
+
+    ensure!(
+        config.is_valid(),
+        "heartbeat interval must be non-zero and shorter than the stale timeout"
+    );
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_target(false)
+        .with_env_filter(EnvFilter::try_new(&args.log_level)?);
+    match args.log_format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+    // Without an API metrics can't be scraped, so none are recorded.
+    let metrics = if args.no_api {
+        None
+    } else {
+        Some(telemetry::install()?)
+    };
 
     let client = Client::connect(
         args.etcd,
@@ -74,20 +222,37 @@ async fn main() -> anyhow::Result<()> {
     )
     .await?
     .kv_client();
+    let client = NamespacedKvClient::new(client, &args.namespace);
 
     let mut tasks = JoinSet::new();
 
-    if let Some(amount) = NonZeroUsize::new(args.workers) {
-        tasks.spawn(worker::run(
-            amount,
-            client.clone(),
-            args.name.unwrap_or_else(default_worker_name),
-        ));
-    }
+    // The worker pool always runs, so a node without capacity can still be
+    // scaled up through the API.
+    let capacity = Arc::new(Capacity::new(Resources::new(cpu, args.memory)));
+    let counters = Arc::new(AcquisitionCounters::default());
+    tasks.spawn(worker::run(
+        capacity.clone(),
+        counters.clone(),
+        client.clone(),
+        args.name.unwrap_or_else(default_worker_name),
+        args.labels,
+        config,
+        Arc::new(Simulation),
+    ));
 
-    if !args.no_api {
+    if let Some(metrics) = metrics {
         tasks.spawn(async move {
-            if let Err(err) = api::run(args.listen, client).await {
+            if let Err(err) = api::run(
+                args.listen,
+                client,
+                capacity,
+                counters,
+                config,
+                metrics,
+                args.drain_period,
+            )
+            .await
+            {
                 tracing::error!("api exited with error: {err}");
                 exit(1);
             }
@@ -0,0 +1,56 @@
+use etcd_client::{
+    DeleteOptions, DeleteResponse, GetOptions, GetResponse, KvClient, KvClientPrefix, PutOptions,
+    PutResponse, Txn, TxnResponse,
+};
+
+use basti_types::Namespace;
+
+/// Cheaply cloneable KV client which scopes all keys to a namespace prefix,
+/// allowing several clusters to share one etcd keyspace.
+#[derive(Clone)]
+pub struct NamespacedKvClient {
+    client: KvClient,
+    prefix: Vec<u8>,
+}
+
+impl NamespacedKvClient {
+    pub fn new(client: KvClient, namespace: &Namespace) -> Self {
+        Self {
+            client,
+            prefix: namespace.prefix(),
+        }
+    }
+
+    fn prefixed(&self) -> KvClientPrefix {
+        KvClientPrefix::new(self.client.clone(), self.prefix.clone())
+    }
+
+    pub async fn get(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<GetOptions>,
+    ) -> Result<GetResponse, etcd_client::Error> {
+        self.prefixed().get(key, options).await
+    }
+
+    pub async fn put(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        value: impl Into<Vec<u8>>,
+        options: Option<PutOptions>,
+    ) -> Result<PutResponse, etcd_client::Error> {
+        self.prefixed().put(key, value, options).await
+    }
+
+    pub async fn delete(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResponse, etcd_client::Error> {
+        self.prefixed().delete(key, options).await
+    }
+
+    pub async fn txn(&mut self, txn: Txn) -> Result<TxnResponse, etcd_client::Error> {
+        self.prefixed().txn(txn).await
+    }
+}
@@ -1,60 +1,244 @@
 use std::time::Duration;
 
 use anyhow::{anyhow, bail};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use etcd_client::{
-    Compare, CompareOp, GetOptions, KvClient, SortOrder, SortTarget, Txn, TxnOp, TxnOpResponse,
+    Compare, CompareOp, DeleteOptions, GetOptions, SortOrder, SortTarget, Txn, TxnOp, TxnOpResponse,
 };
+use metrics::counter;
 use uuid::Uuid;
 
-use basti_types::{PriorityKey, Task, TaskKey, TaskPriority, TaskState, WorkerName};
+use basti_types::{
+    ConcurrencyGroup, ConcurrencyLimit, ConcurrencyLimitKey, ConcurrencySlotKey, ConflictPolicy,
+    CreateTask, DedupeKey, Labels, LogChunk, LogChunkKey, LogStream, PauseRequestKey,
+    PriorityCount, PriorityKey, QueuePausedKey, QueueStats, QueueStatus, RateLimit,
+    RateLimitConfig, RateLimitKey, StateCount, Task, TaskKey, TaskOutcome, TaskPriority, TaskState,
+    TaskValue, Throughput, TokenBucket, TokenBucketKey, UniqueKey, WorkerName,
+};
+
+use crate::{namespace::NamespacedKvClient, telemetry};
+
+/// Default upper bound of operations etcd accepts in a single transaction.
+const MAX_TXN_OPS: usize = 128;
+
+/// Upper bound of operations needed to create a single task.
+const CREATE_TASK_OPS: usize = 4;
+
+#[derive(Debug)]
+pub enum CreateOutcome {
+    Created(Task),
+    /// A task with the same dedupe key or a coalescable unique key exists.
+    Existing(Task),
+    /// A task with the same unique key exists.
+    Conflict(Task),
+}
+
+/// Keys referencing a task by its ID, which are claimed when the task is
+/// created. The unique key is released once the task is done, the dedupe key
+/// only once the task is removed.
+fn reference_keys(task: &Task) -> Vec<Vec<u8>> {
+    let dedupe_key = task.value.dedupe_key.as_deref().map(DedupeKey::new);
+    let unique_key = task.value.unique_key.as_deref().map(UniqueKey::new);
+
+    dedupe_key
+        .as_ref()
+        .map(Vec::from)
+        .into_iter()
+        .chain(unique_key.as_ref().map(Vec::from))
+        .collect()
+}
+
+fn create_task_compares(task: &Task) -> Vec<Compare> {
+    reference_keys(task)
+        .into_iter()
+        .map(|key| Compare::create_revision(key, CompareOp::Equal, 0))
+        .collect()
+}
+
+fn create_task_operations(task: &Task) -> anyhow::Result<Vec<TxnOp>> {
+    let mut operations = vec![
+        TxnOp::put(&task.key, bson::to_vec(task)?, None),
+        put_priority(task)?,
+    ];
+
+    for key in reference_keys(task) {
+        operations.push(TxnOp::put(key, task.key.id.as_bytes().as_slice(), None));
+    }
+
+    Ok(operations)
+}
 
 pub async fn create_task(
-    client: &mut KvClient,
-    duration: Duration,
-    priority: TaskPriority,
-) -> anyhow::Result<Task> {
-    let task = Task::generate(priority, duration);
+    client: &mut NamespacedKvClient,
+    payload: CreateTask,
+) -> anyhow::Result<CreateOutcome> {
+    let _timer = telemetry::time_etcd_operation("create_task");
+    // The task behind an existing reference key may be removed concurrently,
+    // in which case creating it is simply tried again.
+    for _ in 0..3 {
+        let task = Task::generate(payload.clone());
 
-    let txn = Txn::new().and_then([
-        TxnOp::put(&task.key, bson::to_vec(&task)?, None),
-        TxnOp::put(&PriorityKey::from(&task), [], None),
-    ]);
+        let txn = Txn::new()
+            .when(create_task_compares(&task))
+            .and_then(create_task_operations(&task)?)
+            .or_else(
+                reference_keys(&task)
+                    .into_iter()
+                    .map(|key| TxnOp::get(key, None))
+                    .collect::<Vec<_>>(),
+            );
 
-    client.txn(txn).await?;
+        let response = client.txn(txn).await?;
+        if response.succeeded() {
+            return Ok(CreateOutcome::Created(task));
+        }
 
-    Ok(task)
+        let mut references = Vec::new();
+        for op_response in response.op_responses() {
+            if let TxnOpResponse::Get(get_response) = op_response {
+                for kv in get_response.kvs() {
+                    references.push((kv.key().to_vec(), Uuid::from_slice(kv.value())?));
+                }
+            }
+        }
+
+        let referenced_id = |key: Vec<u8>| {
+            references
+                .iter()
+                .find_map(|(reference, id)| (*reference == key).then_some(*id))
+        };
+
+        let dedupe_id = (task.value.dedupe_key.as_deref())
+            .and_then(|key| referenced_id(Vec::from(&DedupeKey::new(key))));
+        let unique_id = (task.value.unique_key.as_deref())
+            .and_then(|key| referenced_id(Vec::from(&UniqueKey::new(key))));
+
+        let (existing_id, outcome): (_, fn(Task) -> CreateOutcome) = match (dedupe_id, unique_id) {
+            (Some(id), _) => (id, CreateOutcome::Existing),
+            (None, Some(id)) => match payload.on_conflict {
+                ConflictPolicy::Reject => (id, CreateOutcome::Conflict),
+                ConflictPolicy::Coalesce => (id, CreateOutcome::Existing),
+            },
+            (None, None) => continue,
+        };
+
+        if let Some((task, _)) = find_task(client, existing_id, &TaskState::VARIANTS).await? {
+            return Ok(outcome(task));
+        }
+    }
+
+    bail!("could not create task due to contention on its reference keys")
+}
+
+/// Creates tasks in chunked transactions. Chunks are not atomic with respect
+/// to each other, chunks committed before a failing one are kept.
+pub async fn create_tasks(
+    client: &mut NamespacedKvClient,
+    payloads: Vec<CreateTask>,
+) -> anyhow::Result<Vec<CreateOutcome>> {
+    let _timer = telemetry::time_etcd_operation("create_tasks");
+    let mut outcomes = Vec::with_capacity(payloads.len());
+
+    for chunk in payloads.chunks(MAX_TXN_OPS / CREATE_TASK_OPS) {
+        let tasks = chunk
+            .iter()
+            .cloned()
+            .map(Task::generate)
+            .collect::<Vec<_>>();
+
+        let mut compares = Vec::new();
+        let mut operations = Vec::with_capacity(MAX_TXN_OPS);
+        for task in &tasks {
+            compares.extend(create_task_compares(task));
+            operations.extend(create_task_operations(task)?);
+        }
+
+        // etcd rejects transactions that put the same key several times.
+        let mut claimed_keys = tasks.iter().flat_map(reference_keys).collect::<Vec<_>>();
+        let claimed_keys_count = claimed_keys.len();
+        claimed_keys.sort_unstable();
+        claimed_keys.dedup();
+
+        if claimed_keys.len() == claimed_keys_count
+            && client
+                .txn(Txn::new().when(compares).and_then(operations))
+                .await?
+                .succeeded()
+        {
+            outcomes.extend(tasks.into_iter().map(CreateOutcome::Created));
+            continue;
+        }
+
+        // Some reference keys of this chunk exist already or are shared, resolve
+        // them one by one.
+        for payload in chunk {
+            outcomes.push(create_task(client, payload.clone()).await?);
+        }
+    }
+
+    Ok(outcomes)
+}
+
+/// Puts the priority key of a task. Its value holds the task's selector, so
+/// workers can skip tasks they can't run without reading them.
+fn put_priority(task: &Task) -> anyhow::Result<TxnOp> {
+    let value = if task.value.selector.is_empty() {
+        Vec::new()
+    } else {
+        bson::to_vec(&task.value.selector)?
+    };
+
+    Ok(TxnOp::put(&PriorityKey::from(task), value, None))
 }
 
+/// Lists priority keys along with the selectors of their tasks, starting
+/// after the given key.
 pub async fn list_priorities(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
+    after: Option<&PriorityKey>,
     limit: i64,
-) -> anyhow::Result<Vec<PriorityKey>> {
+) -> anyhow::Result<Vec<(PriorityKey, Labels)>> {
+    let _timer = telemetry::time_etcd_operation("list_priorities");
+    let start = match after {
+        None => vec![PriorityKey::PREFIX],
+        Some(key) => {
+            let mut start = Vec::from(key);
+            start.push(0);
+            start
+        }
+    };
+
     let response = client
         .get(
-            [PriorityKey::PREFIX],
+            start,
             Some(
                 GetOptions::default()
                     .with_limit(limit)
                     .with_sort(SortTarget::Key, SortOrder::Ascend)
-                    .with_prefix(),
+                    .with_range([PriorityKey::PREFIX + 1]),
             ),
         )
         .await?;
 
     let mut priorities = Vec::new();
     for kv in response.kvs() {
-        priorities.push(PriorityKey::try_from(kv.key())?);
+        let selector = if kv.value().is_empty() {
+            Labels::default()
+        } else {
+            bson::from_slice(kv.value())?
+        };
+        priorities.push((PriorityKey::try_from(kv.key())?, selector));
     }
 
     Ok(priorities)
 }
 
 pub async fn list_tasks(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
     state: Option<TaskState>,
     limit: i64,
 ) -> anyhow::Result<Vec<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("list_tasks");
     let key = match state {
         None => vec![TaskKey::PREFIX],
         Some(state) => vec![TaskKey::PREFIX, state.into()],
@@ -81,11 +265,52 @@ pub async fn list_tasks(
     Ok(tasks)
 }
 
+/// Lists tasks of a state in key order, starting after the given key.
+pub async fn list_tasks_after(
+    client: &mut NamespacedKvClient,
+    state: TaskState,
+    after: Option<&[u8]>,
+    limit: i64,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("list_tasks_after");
+    let prefix = vec![TaskKey::PREFIX, state.into()];
+    let start = match after {
+        None => prefix.clone(),
+        Some(key) => {
+            let mut start = key.to_vec();
+            start.push(0);
+            start
+        }
+    };
+    let end = vec![TaskKey::PREFIX, u8::from(state) + 1];
+
+    let response = client
+        .get(
+            start,
+            Some(GetOptions::default().with_range(end).with_limit(limit)),
+        )
+        .await?;
+
+    let mut tasks = Vec::new();
+    for kv in response.kvs() {
+        tasks.push((
+            Task {
+                key: TaskKey::try_from(kv.key())?,
+                value: bson::from_slice(kv.value())?,
+            },
+            kv.mod_revision(),
+        ));
+    }
+
+    Ok(tasks)
+}
+
 pub async fn find_task(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
     id: Uuid,
     try_states: &[TaskState],
 ) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("find_task");
     let txn = Txn::new().and_then(
         try_states
             .iter()
@@ -118,18 +343,56 @@ pub async fn find_task(
     Ok(Some((task, kv.mod_revision())))
 }
 
+/// Gets several tasks in the given state within a single transaction,
+/// skipping the ones which aren't in that state.
+pub async fn find_tasks(
+    client: &mut NamespacedKvClient,
+    ids: &[Uuid],
+    state: TaskState,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("find_tasks");
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let txn = Txn::new().and_then(
+        ids.iter()
+            .map(|id| TxnOp::get(&TaskKey::new(state, *id), None))
+            .collect::<Vec<_>>(),
+    );
+
+    let response = client.txn(txn).await?;
+
+    let mut tasks = Vec::new();
+    for op_response in response.op_responses() {
+        let TxnOpResponse::Get(get_response) = op_response else {
+            continue;
+        };
+
+        for kv in get_response.kvs() {
+            let task = Task {
+                key: TaskKey::try_from(kv.key())?,
+                value: bson::from_slice(kv.value())?,
+            };
+            tasks.push((task, kv.mod_revision()));
+        }
+    }
+
+    Ok(tasks)
+}
+
 async fn update_task_with_revision(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
     revision: i64,
     old_key: &TaskKey,
     new_key: &TaskKey,
+    mut compares: Vec<Compare>,
     mut operations: Vec<TxnOp>,
 ) -> anyhow::Result<Option<i64>> {
+    compares.push(Compare::mod_revision(old_key, CompareOp::Equal, revision));
     operations.push(TxnOp::get(new_key, None));
 
-    let txn = Txn::new()
-        .when([Compare::mod_revision(old_key, CompareOp::Equal, revision)])
-        .and_then(operations);
+    let txn = Txn::new().when(compares).and_then(operations);
 
     let response = client.txn(txn).await.map_err(anyhow::Error::from)?;
 
@@ -152,80 +415,342 @@ async fn update_task_with_revision(
 }
 
 pub async fn requeue_task(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
     mut task: Task,
     revision: i64,
 ) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("requeue_task");
     let initial_key = task.key;
 
     task.key.state = TaskState::Queued;
     task.value.updated_at = Utc::now();
+    task.value.queued_at = Some(task.value.updated_at);
     task.value.assignee = None;
 
+    let mut operations = vec![TxnOp::delete(&initial_key, None)];
+    operations.extend(release_concurrency_slot(&mut task));
+    operations.extend([
+        TxnOp::put(
+            &task.key,
+            bson::to_vec(&task).map_err(anyhow::Error::from)?,
+            None,
+        ),
+        put_priority(&task)?,
+    ]);
+
     Ok(update_task_with_revision(
         client,
         revision,
         &initial_key,
         &task.key,
-        vec![
-            TxnOp::delete(&initial_key, None),
-            TxnOp::put(
-                &task.key,
-                bson::to_vec(&task).map_err(anyhow::Error::from)?,
-                None,
-            ),
-            TxnOp::put(&PriorityKey::from(&task), "", None),
-        ],
+        vec![],
+        operations,
     )
     .await?
     .map(|revision| (task, revision)))
 }
 
-pub async fn acquire_task(
-    client: &mut KvClient,
-    mut task: Task,
-    revision: i64,
+/// Claims on cluster-wide limits under which a task is acquired.
+#[derive(Debug, Default)]
+pub struct Admission {
+    concurrency_slot: Option<u32>,
+    /// Token bucket with the task's token taken, and the revision of its key.
+    token_bucket: Option<(TokenBucket, i64)>,
+}
+
+/// Checks the concurrency and rate limits of a task's group, returning `None`
+/// if starting the task now would exceed either of them.
+pub async fn admit_task(
+    client: &mut NamespacedKvClient,
+    task: &Task,
+) -> anyhow::Result<Option<Admission>> {
+    let _timer = telemetry::time_etcd_operation("admit_task");
+    let Some(group) = &task.value.concurrency_group else {
+        return Ok(Some(Admission::default()));
+    };
+
+    let Some(concurrency_slot) = find_free_concurrency_slot(client, group).await? else {
+        return Ok(None);
+    };
+
+    let token_bucket = match find_rate_limit(client, group).await? {
+        None => None,
+        Some(config) => {
+            let now = Utc::now();
+            let (bucket, revision) = find_token_bucket(client, group)
+                .await?
+                .unwrap_or_else(|| (TokenBucket::full(&config, now), 0));
+
+            let Some(bucket) = bucket.refill(&config, now).take() else {
+                return Ok(None);
+            };
+
+            Some((bucket, revision))
+        }
+    };
+
+    Ok(Some(Admission {
+        concurrency_slot: Some(concurrency_slot),
+        token_bucket,
+    }))
+}
+
+/// Marks a task as running, returning the compares and operations claiming
+/// its admission.
+fn acquire_task_operations(
+    task: &mut Task,
     name: WorkerName,
-) -> anyhow::Result<Option<(Task, i64)>> {
+    admission: Admission,
+) -> anyhow::Result<(Vec<Compare>, Vec<TxnOp>)> {
     let initial_key = task.key;
 
     task.key.state = TaskState::Running;
     task.value.assignee = Some(name);
     task.value.updated_at = Utc::now();
+    task.value.started_at.get_or_insert(task.value.updated_at);
+    task.value.attempts += 1;
+    task.value.concurrency_slot = admission.concurrency_slot;
+
+    let mut compares = Vec::new();
+    let mut operations = vec![
+        TxnOp::delete(&initial_key, None),
+        TxnOp::delete(&PriorityKey::from(&*task), None),
+        TxnOp::put(
+            &task.key,
+            bson::to_vec(&task).map_err(anyhow::Error::from)?,
+            None,
+        ),
+    ];
+
+    if let Some(group) = &task.value.concurrency_group {
+        if let Some(slot) = admission.concurrency_slot {
+            let slot_key = ConcurrencySlotKey::new(group, slot);
+            compares.push(Compare::create_revision(&slot_key, CompareOp::Equal, 0));
+            operations.push(TxnOp::put(
+                &slot_key,
+                task.key.id.as_bytes().as_slice(),
+                None,
+            ));
+        }
+
+        if let Some((bucket, bucket_revision)) = admission.token_bucket {
+            let bucket_key = TokenBucketKey::new(group);
+            compares.push(Compare::mod_revision(
+                &bucket_key,
+                CompareOp::Equal,
+                bucket_revision,
+            ));
+            operations.push(TxnOp::put(&bucket_key, bson::to_vec(&bucket)?, None));
+        }
+    }
+
+    Ok((compares, operations))
+}
+
+pub async fn acquire_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+    name: WorkerName,
+    admission: Admission,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("acquire_task");
+    let initial_key = task.key;
+    let (compares, operations) = acquire_task_operations(&mut task, name, admission)?;
 
     Ok(update_task_with_revision(
         client,
         revision,
         &initial_key,
         &task.key,
-        vec![
-            TxnOp::delete(&initial_key, None),
-            TxnOp::delete(&PriorityKey::from(&task), None),
-            TxnOp::put(
-                &task.key,
-                bson::to_vec(&task).map_err(anyhow::Error::from)?,
-                None,
-            ),
-        ],
+        compares,
+        operations,
     )
     .await?
     .map(|revision| (task, revision)))
 }
 
+/// Acquires several tasks outside of any concurrency group in a single
+/// transaction. Either all of them are acquired or none, e.g. if one of them
+/// was stolen in the meantime.
+pub async fn acquire_tasks(
+    client: &mut NamespacedKvClient,
+    tasks: Vec<(Task, i64)>,
+    name: WorkerName,
+) -> anyhow::Result<Option<Vec<(Task, i64)>>> {
+    let _timer = telemetry::time_etcd_operation("acquire_tasks");
+    let mut compares = Vec::new();
+    let mut operations = Vec::new();
+    let mut acquired = Vec::new();
+
+    for (mut task, revision) in tasks {
+        if task.value.concurrency_group.is_some() {
+            bail!("task {} needs admission to be acquired", task.key.id);
+        }
+
+        compares.push(Compare::mod_revision(&task.key, CompareOp::Equal, revision));
+        let (task_compares, task_operations) =
+            acquire_task_operations(&mut task, name.clone(), Admission::default())?;
+        compares.extend(task_compares);
+        operations.extend(task_operations);
+        acquired.push(task);
+    }
+
+    let txn = Txn::new().when(compares).and_then(operations);
+    let response = client.txn(txn).await?;
+
+    if !response.succeeded() {
+        return Ok(None);
+    }
+
+    // All keys written by a transaction share its revision.
+    let revision = response
+        .header()
+        .ok_or_else(|| anyhow!("transaction response has no header"))?
+        .revision();
+
+    Ok(Some(
+        acquired.into_iter().map(|task| (task, revision)).collect(),
+    ))
+}
+
+/// Clears the concurrency slot held by a task, returning the operation which
+/// frees it.
+fn release_concurrency_slot(task: &mut Task) -> Option<TxnOp> {
+    let slot = task.value.concurrency_slot.take()?;
+    let group = task.value.concurrency_group.as_ref()?;
+    Some(TxnOp::delete(&ConcurrencySlotKey::new(group, slot), None))
+}
+
+/// Finds the lowest free slot of a concurrency group below its limit.
+async fn find_free_concurrency_slot(
+    client: &mut NamespacedKvClient,
+    group: &ConcurrencyGroup,
+) -> anyhow::Result<Option<u32>> {
+    let limit = find_concurrency_limit(client, group).await?;
+    let taken = list_concurrency_slots(client, group).await?;
+    let limit = limit.unwrap_or(u32::MAX);
+
+    // Slots at or above a lowered limit are held until their tasks are done,
+    // and count towards the limit until then.
+    if taken.len() >= limit as usize {
+        return Ok(None);
+    }
+
+    Ok((0..limit).find(|slot| !taken.contains(slot)))
+}
+
+async fn find_concurrency_limit(
+    client: &mut NamespacedKvClient,
+    group: &ConcurrencyGroup,
+) -> anyhow::Result<Option<u32>> {
+    let response = client.get(&ConcurrencyLimitKey::new(group), None).await?;
+
+    let Some(kv) = response.kvs().first() else {
+        return Ok(None);
+    };
+
+    Ok(Some(kv.value_str()?.parse()?))
+}
+
+async fn list_concurrency_slots(
+    client: &mut NamespacedKvClient,
+    group: &ConcurrencyGroup,
+) -> anyhow::Result<Vec<u32>> {
+    let response = client
+        .get(
+            ConcurrencySlotKey::group_prefix(group),
+            Some(GetOptions::default().with_prefix().with_keys_only()),
+        )
+        .await?;
+
+    let mut slots = Vec::new();
+    for kv in response.kvs() {
+        slots.push(ConcurrencySlotKey::slot_from_key(group, kv.key())?);
+    }
+
+    Ok(slots)
+}
+
+pub async fn list_concurrency_limits(
+    client: &mut NamespacedKvClient,
+) -> anyhow::Result<Vec<ConcurrencyLimit>> {
+    let _timer = telemetry::time_etcd_operation("list_concurrency_limits");
+    let response = client
+        .get(
+            [ConcurrencyLimitKey::PREFIX],
+            Some(GetOptions::default().with_prefix()),
+        )
+        .await?;
+
+    let mut limits = Vec::new();
+    for kv in response.kvs() {
+        let group = ConcurrencyLimitKey::group_from_key(kv.key())?;
+        let running = list_concurrency_slots(client, &group).await?.len();
+        limits.push(ConcurrencyLimit {
+            group,
+            limit: Some(kv.value_str()?.parse()?),
+            running: u32::try_from(running)?,
+        });
+    }
+
+    Ok(limits)
+}
+
+pub async fn set_concurrency_limit(
+    client: &mut NamespacedKvClient,
+    group: ConcurrencyGroup,
+    limit: Option<u32>,
+) -> anyhow::Result<ConcurrencyLimit> {
+    let _timer = telemetry::time_etcd_operation("set_concurrency_limit");
+    let key = ConcurrencyLimitKey::new(&group);
+    if let Some(limit) = limit {
+        client.put(&key, limit.to_string(), None).await?;
+    } else {
+        client.delete(&key, None).await?;
+    }
+
+    let running = list_concurrency_slots(client, &group).await?.len();
+    Ok(ConcurrencyLimit {
+        group,
+        limit,
+        running: u32::try_from(running)?,
+    })
+}
+
+/// Progress a task's handler reported since the last heartbeat.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReport {
+    /// Fraction of the task which is done.
+    pub fraction: Option<f32>,
+    /// State to resume from if the task is started again, replacing the
+    /// previous checkpoint.
+    pub checkpoint: Option<serde_json::Value>,
+}
+
 pub async fn progress_task(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
     mut task: Task,
     revision: i64,
-    progress: Duration,
+    report: ProgressReport,
 ) -> anyhow::Result<Option<(Task, i64)>> {
-    task.value.remaining -= progress;
+    let _timer = telemetry::time_etcd_operation("progress_task");
     task.value.updated_at = Utc::now();
+    if let Some(fraction) = report.fraction.filter(|fraction| !fraction.is_nan()) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        task.value.progress = Some(fraction);
+        task.value.remaining = task.value.duration.mul_f32(1.0 - fraction);
+    }
+    if let Some(checkpoint) = report.checkpoint {
+        task.value.checkpoint = Some(checkpoint);
+    }
 
     Ok(update_task_with_revision(
         client,
         revision,
         &task.key,
         &task.key,
+        vec![],
         vec![TxnOp::put(
             &task.key,
             bson::to_vec(&task).map_err(anyhow::Error::from)?,
@@ -236,15 +761,219 @@ pub async fn progress_task(
     .map(|revision| (task, revision)))
 }
 
-pub async fn cancel_task(client: &mut KvClient, id: Uuid) -> anyhow::Result<Option<Task>> {
+pub async fn reprioritize_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+    priority: TaskPriority,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("reprioritize_task");
+    let initial_priority_key = PriorityKey::from(&task);
+
+    task.value.priority = priority;
+    task.value.updated_at = Utc::now();
+
+    let mut operations = vec![TxnOp::put(
+        &task.key,
+        bson::to_vec(&task).map_err(anyhow::Error::from)?,
+        None,
+    )];
+
+    // etcd rejects transactions that delete and put the same key.
+    if initial_priority_key.priority != priority {
+        operations.push(TxnOp::delete(&initial_priority_key, None));
+        operations.push(put_priority(&task)?);
+    }
+
+    Ok(
+        update_task_with_revision(client, revision, &task.key, &task.key, vec![], operations)
+            .await?
+            .map(|revision| (task, revision)),
+    )
+}
+
+pub async fn pause_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("pause_task");
+    let initial_key = task.key;
+
+    task.key.state = TaskState::Paused;
+    task.value.assignee = None;
+    task.value.updated_at = Utc::now();
+
+    let release_operation = release_concurrency_slot(&mut task);
+    let mut operations = vec![
+        TxnOp::delete(&initial_key, None),
+        TxnOp::delete(&PauseRequestKey::new(task.key.id), None),
+        TxnOp::put(
+            &task.key,
+            bson::to_vec(&task).map_err(anyhow::Error::from)?,
+            None,
+        ),
+    ];
+
+    if initial_key.state == TaskState::Queued {
+        operations.push(TxnOp::delete(&PriorityKey::from(&task), None));
+    }
+    operations.extend(release_operation);
+
+    Ok(update_task_with_revision(
+        client,
+        revision,
+        &initial_key,
+        &task.key,
+        vec![],
+        operations,
+    )
+    .await?
+    .map(|revision| (task, revision)))
+}
+
+/// Moves a queued or running task to the failed state, recording why it
+/// failed.
+pub async fn fail_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+    outcome: TaskOutcome,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("fail_task");
+    task.value.outcome = Some(outcome);
+    terminate_task(client, task, revision, TaskState::Failed).await
+}
+
+/// Moves a running task to the finished state, storing its result.
+pub async fn finish_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+    result: Option<serde_json::Value>,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("finish_task");
+    task.value.result = result;
+    terminate_task(client, task, revision, TaskState::Finished).await
+}
+
+/// Moves a task to a terminal state. Terminal tasks are kept until cancelled
+/// or past the retention period, but release their priority key, concurrency
+/// slot and reference keys.
+async fn terminate_task(
+    client: &mut NamespacedKvClient,
+    mut task: Task,
+    revision: i64,
+    state: TaskState,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let initial_key = task.key;
+
+    task.key.state = state;
+    task.value.assignee = None;
+    task.value.updated_at = Utc::now();
+
+    let release_operation = release_concurrency_slot(&mut task);
+    let mut operations = vec![
+        TxnOp::delete(&initial_key, None),
+        TxnOp::delete(&PauseRequestKey::new(task.key.id), None),
+        TxnOp::put(
+            &task.key,
+            bson::to_vec(&task).map_err(anyhow::Error::from)?,
+            None,
+        ),
+    ];
+
+    if initial_key.state == TaskState::Queued {
+        operations.push(TxnOp::delete(&PriorityKey::from(&task), None));
+    }
+    operations.extend(release_operation);
+    // Submissions retried after the task is done still find it by its dedupe
+    // key, which is kept until the task is removed.
+    if let Some(key) = task.value.unique_key.as_deref() {
+        operations.push(TxnOp::delete(&UniqueKey::new(key), None));
+    }
+
+    let Some(revision) = update_task_with_revision(
+        client,
+        revision,
+        &initial_key,
+        &task.key,
+        vec![],
+        operations,
+    )
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    match &task.value.outcome {
+        None => counter!(telemetry::TASKS_FINISHED).increment(1),
+        Some(outcome) => {
+            counter!(telemetry::TASKS_FAILED, "outcome" => outcome.to_string()).increment(1);
+        }
+    }
+    if let Some(started_at) = task.value.started_at {
+        // Clocks of the nodes which started and terminated the task may be
+        // skewed, so it might seem to have taken a negative time.
+        if let Ok(started_for) = (task.value.updated_at - started_at).to_std() {
+            telemetry::record_task_run(started_for);
+        }
+    }
+
+    Ok(Some((task, revision)))
+}
+
+pub async fn request_pause(client: &mut NamespacedKvClient, id: Uuid) -> anyhow::Result<bool> {
+    let _timer = telemetry::time_etcd_operation("request_pause");
+    let running_key = TaskKey::new(TaskState::Running, id);
+
+    let txn = Txn::new()
+        .when([Compare::version(&running_key, CompareOp::Greater, 0)])
+        .and_then([TxnOp::put(&PauseRequestKey::new(id), [], None)]);
+
+    Ok(client.txn(txn).await?.succeeded())
+}
+
+pub async fn find_pause_request(client: &mut NamespacedKvClient, id: Uuid) -> anyhow::Result<bool> {
+    let _timer = telemetry::time_etcd_operation("find_pause_request");
+    let response = client
+        .get(
+            &PauseRequestKey::new(id),
+            Some(GetOptions::default().with_count_only()),
+        )
+        .await?;
+
+    Ok(response.count() > 0)
+}
+
+pub async fn cancel_task(
+    client: &mut NamespacedKvClient,
+    id: Uuid,
+) -> anyhow::Result<Option<Task>> {
+    let _timer = telemetry::time_etcd_operation("cancel_task");
     let Some((task, _)) = find_task(client, id, &TaskState::VARIANTS).await? else {
         return Ok(None);
     };
 
-    let operations = TaskState::VARIANTS
+    let mut operations = TaskState::VARIANTS
         .iter()
         .map(|state| TxnOp::delete(&TaskKey::new(*state, id), None))
         .collect::<Vec<_>>();
+    operations.push(TxnOp::delete(&PauseRequestKey::new(id), None));
+    if task.key.state == TaskState::Queued {
+        operations.push(TxnOp::delete(&PriorityKey::from(&task), None));
+    }
+    operations.push(TxnOp::delete(
+        LogChunkKey::task_prefix(id),
+        Some(DeleteOptions::new().with_prefix()),
+    ));
+    for key in reference_keys(&task) {
+        operations.push(TxnOp::delete(key, None));
+    }
+    if let (Some(group), Some(slot)) = (&task.value.concurrency_group, task.value.concurrency_slot)
+    {
+        operations.push(TxnOp::delete(&ConcurrencySlotKey::new(group, slot), None));
+    }
 
     let txn = Txn::new().and_then(operations);
     client.txn(txn).await?;
@@ -252,27 +981,443 @@ pub async fn cancel_task(client: &mut KvClient, id: Uuid) -> anyhow::Result<Opti
     Ok(Some(task))
 }
 
-pub async fn finish_task(
-    client: &mut KvClient,
-    key: &TaskKey,
+/// Lists done tasks of a state in the order they were done, oldest first.
+pub async fn list_done_tasks(
+    client: &mut NamespacedKvClient,
+    state: TaskState,
+    limit: i64,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    let _timer = telemetry::time_etcd_operation("list_done_tasks");
+    // Done tasks aren't modified anymore, so their modification revision is
+    // the one at which they were done.
+    let response = client
+        .get(
+            vec![TaskKey::PREFIX, state.into()],
+            Some(
+                GetOptions::default()
+                    .with_prefix()
+                    .with_sort(SortTarget::Mod, SortOrder::Ascend)
+                    .with_limit(limit),
+            ),
+        )
+        .await?;
+
+    let mut tasks = Vec::new();
+    for kv in response.kvs() {
+        tasks.push((
+            Task {
+                key: TaskKey::try_from(kv.key())?,
+                value: bson::from_slice(kv.value())?,
+            },
+            kv.mod_revision(),
+        ));
+    }
+
+    Ok(tasks)
+}
+
+/// Deletes a done task along with its output and dedupe key, unless it was
+/// modified since the given revision.
+pub async fn delete_done_task(
+    client: &mut NamespacedKvClient,
+    task: &Task,
     revision: i64,
-) -> anyhow::Result<Option<()>> {
+) -> anyhow::Result<bool> {
+    let _timer = telemetry::time_etcd_operation("delete_done_task");
+    let mut operations = vec![
+        TxnOp::delete(&task.key, None),
+        TxnOp::delete(
+            LogChunkKey::task_prefix(task.key.id),
+            Some(DeleteOptions::new().with_prefix()),
+        ),
+    ];
+    if let Some(key) = task.value.dedupe_key.as_deref() {
+        operations.push(TxnOp::delete(&DedupeKey::new(key), None));
+    }
+
     let txn = Txn::new()
-        .when([Compare::mod_revision(
-            &key.clone(),
-            CompareOp::Equal,
-            revision,
-        )])
-        .and_then([TxnOp::delete(key, None)]);
-
-    if !client
-        .txn(txn)
-        .await
-        .map_err(anyhow::Error::from)?
-        .succeeded()
-    {
+        .when([Compare::mod_revision(&task.key, CompareOp::Equal, revision)])
+        .and_then(operations);
+
+    Ok(client.txn(txn).await?.succeeded())
+}
+
+/// Appends output to the logs of a task, split into chunks of at most the
+/// maximum chunk size. Output beyond the maximum number of chunks is dropped.
+pub async fn append_log(
+    client: &mut NamespacedKvClient,
+    id: Uuid,
+    stream: LogStream,
+    data: &str,
+) -> anyhow::Result<()> {
+    let _timer = telemetry::time_etcd_operation("append_log");
+    let mut next_seq = None;
+    let mut rest = data;
+
+    while !rest.is_empty() {
+        let mut end = rest.len().min(LogChunkKey::MAX_CHUNK_SIZE);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let (chunk, remainder) = rest.split_at(end);
+        let Some(seq) = append_log_chunk(client, id, stream, chunk, next_seq).await? else {
+            return Ok(());
+        };
+
+        next_seq = Some(seq + 1);
+        rest = remainder;
+    }
+
+    Ok(())
+}
+
+/// Stored as the last chunk of a task's output instead of the output itself.
+const LOG_TRUNCATED: &str = "[output truncated]\n";
+
+/// Stores a chunk of output after the last one, starting with `seq` if it is
+/// known. Returns the sequence number of the chunk, or `None` once the output
+/// is truncated.
+async fn append_log_chunk(
+    client: &mut NamespacedKvClient,
+    id: Uuid,
+    stream: LogStream,
+    data: &str,
+    mut next_seq: Option<u32>,
+) -> anyhow::Result<Option<u32>> {
+    for _ in 0..3 {
+        let seq = match next_seq.take() {
+            Some(seq) => seq,
+            None => {
+                let response = client
+                    .get(
+                        LogChunkKey::task_prefix(id),
+                        Some(
+                            GetOptions::default()
+                                .with_prefix()
+                                .with_keys_only()
+                                .with_sort(SortTarget::Key, SortOrder::Descend)
+                                .with_limit(1),
+                        ),
+                    )
+                    .await?;
+
+                match response.kvs().first() {
+                    None => 0,
+                    Some(kv) => LogChunkKey::try_from(kv.key())?.seq + 1,
+                }
+            }
+        };
+
+        if seq >= LogChunkKey::MAX_CHUNKS {
+            return Ok(None);
+        }
+
+        let truncated = seq == LogChunkKey::MAX_CHUNKS - 1;
+        let key = LogChunkKey::new(id, seq);
+        let chunk = if truncated {
+            LogChunk {
+                seq,
+                stream: LogStream::Stderr,
+                data: LOG_TRUNCATED.to_string(),
+                written_at: Utc::now(),
+            }
+        } else {
+            LogChunk {
+                seq,
+                stream,
+                data: data.to_string(),
+                written_at: Utc::now(),
+            }
+        };
+
+        let txn = Txn::new()
+            .when([Compare::create_revision(&key, CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(&key, bson::to_vec(&chunk)?, None)]);
+
+        if client.txn(txn).await?.succeeded() {
+            return Ok((!truncated).then_some(seq));
+        }
+    }
+
+    bail!("failed to append log chunk due to contention")
+}
+
+/// Lists the output of a task following the given sequence number.
+pub async fn list_logs(
+    client: &mut NamespacedKvClient,
+    id: Uuid,
+    after: Option<u32>,
+) -> anyhow::Result<Vec<LogChunk>> {
+    let _timer = telemetry::time_etcd_operation("list_logs");
+    let start = match after {
+        None => LogChunkKey::new(id, 0),
+        Some(seq) => LogChunkKey::new(id, seq.saturating_add(1)),
+    };
+
+    let end = LogChunkKey::new(id, u32::MAX);
+
+    let response = client
+        .get(&start, Some(GetOptions::default().with_range(&end)))
+        .await?;
+
+    let mut chunks = Vec::new();
+    for kv in response.kvs() {
+        chunks.push(bson::from_slice(kv.value())?);
+    }
+
+    Ok(chunks)
+}
+
+pub async fn queue_status(client: &mut NamespacedKvClient) -> anyhow::Result<QueueStatus> {
+    let _timer = telemetry::time_etcd_operation("queue_status");
+    let response = client
+        .get(
+            &QueuePausedKey,
+            Some(GetOptions::default().with_count_only()),
+        )
+        .await?;
+
+    Ok(QueueStatus {
+        paused: response.count() > 0,
+    })
+}
+
+/// Windows of time over which the throughput of the queue is reported.
+const THROUGHPUT_WINDOWS: [Duration; 3] = [
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+];
+
+/// Number of terminal tasks fetched at once while counting throughput.
+const THROUGHPUT_BATCH_SIZE: i64 = 100;
+
+pub async fn queue_stats(client: &mut NamespacedKvClient) -> anyhow::Result<QueueStats> {
+    let _timer = telemetry::time_etcd_operation("queue_stats");
+    let now = Utc::now();
+    let paused = queue_status(client).await?.paused;
+
+    let mut states = Vec::new();
+    for state in TaskState::VARIANTS {
+        states.push(StateCount {
+            state,
+            tasks: count_tasks(client, state).await?,
+        });
+    }
+
+    // Priority keys are sorted by priority, so the next priority with queued
+    // tasks is the one of the first key after the previous priority's range.
+    let mut priorities = Vec::new();
+    let mut start = vec![PriorityKey::PREFIX];
+    loop {
+        let response = client
+            .get(
+                start,
+                Some(
+                    GetOptions::default()
+                        .with_range([PriorityKey::PREFIX + 1])
+                        .with_keys_only()
+                        .with_limit(1),
+                ),
+            )
+            .await?;
+        let Some(kv) = response.kvs().first() else {
+            break;
+        };
+
+        let priority = PriorityKey::try_from(kv.key())?.priority;
+        priorities.push(PriorityCount {
+            priority,
+            tasks: count_keys(client, vec![PriorityKey::PREFIX, priority.0]).await?,
+        });
+
+        let Some(next) = priority.0.checked_add(1) else {
+            break;
+        };
+        start = vec![PriorityKey::PREFIX, next];
+    }
+
+    // Queued tasks are put under a new key whenever they are (re-)queued, so
+    // the key created first belongs to the task which has waited the longest.
+    let response = client
+        .get(
+            vec![TaskKey::PREFIX, TaskState::Queued.into()],
+            Some(
+                GetOptions::default()
+                    .with_prefix()
+                    .with_sort(SortTarget::Create, SortOrder::Ascend)
+                    .with_limit(1),
+            ),
+        )
+        .await?;
+    let oldest_queued = match response.kvs().first() {
+        None => None,
+        Some(kv) => {
+            let value: TaskValue = bson::from_slice(kv.value())?;
+            (now - value.queued_at.unwrap_or(value.created_at))
+                .to_std()
+                .ok()
+        }
+    };
+
+    let finished = terminated_since(client, TaskState::Finished, now).await?;
+    let failed = terminated_since(client, TaskState::Failed, now).await?;
+    let throughput = THROUGHPUT_WINDOWS
+        .into_iter()
+        .map(|window| {
+            let since = now - window;
+            Throughput {
+                window,
+                finished: finished.iter().filter(|time| **time >= since).count() as u64,
+                failed: failed.iter().filter(|time| **time >= since).count() as u64,
+            }
+        })
+        .collect();
+
+    Ok(QueueStats {
+        paused,
+        states,
+        priorities,
+        groups: list_concurrency_limits(client).await?,
+        oldest_queued,
+        throughput,
+    })
+}
+
+pub async fn count_tasks(client: &mut NamespacedKvClient, state: TaskState) -> anyhow::Result<u64> {
+    let _timer = telemetry::time_etcd_operation("count_tasks");
+    count_keys(client, vec![TaskKey::PREFIX, state.into()]).await
+}
+
+async fn count_keys(client: &mut NamespacedKvClient, prefix: Vec<u8>) -> anyhow::Result<u64> {
+    let response = client
+        .get(
+            prefix,
+            Some(GetOptions::default().with_prefix().with_count_only()),
+        )
+        .await?;
+
+    Ok(u64::try_from(response.count())?)
+}
+
+/// Times at which tasks entered a terminal state within the longest
+/// throughput window before `now`, newest first. Counts aren't kept anywhere,
+/// so this reads every task done within the window, values included.
+async fn terminated_since(
+    client: &mut NamespacedKvClient,
+    state: TaskState,
+    now: DateTime<Utc>,
+) -> anyhow::Result<Vec<DateTime<Utc>>> {
+    let since = now - THROUGHPUT_WINDOWS[THROUGHPUT_WINDOWS.len() - 1];
+
+    // Terminal tasks aren't modified anymore, so walking them by descending
+    // modification revision visits the most recently terminated ones first.
+    let mut times = Vec::new();
+    let mut max_revision = None;
+    loop {
+        let mut options = GetOptions::default()
+            .with_prefix()
+            .with_sort(SortTarget::Mod, SortOrder::Descend)
+            .with_limit(THROUGHPUT_BATCH_SIZE);
+        if let Some(revision) = max_revision {
+            options = options.with_max_mod_revision(revision);
+        }
+
+        let response = client
+            .get(vec![TaskKey::PREFIX, state.into()], Some(options))
+            .await?;
+
+        for kv in response.kvs() {
+            let value: TaskValue = bson::from_slice(kv.value())?;
+            if value.updated_at < since {
+                return Ok(times);
+            }
+
+            times.push(value.updated_at);
+            max_revision = Some(kv.mod_revision() - 1);
+        }
+
+        if response.kvs().len() < THROUGHPUT_BATCH_SIZE as usize {
+            return Ok(times);
+        }
+    }
+}
+
+pub async fn set_queue_paused(
+    client: &mut NamespacedKvClient,
+    paused: bool,
+) -> anyhow::Result<QueueStatus> {
+    let _timer = telemetry::time_etcd_operation("set_queue_paused");
+    if paused {
+        client.put(&QueuePausedKey, [], None).await?;
+    } else {
+        client.delete(&QueuePausedKey, None).await?;
+    }
+
+    Ok(QueueStatus { paused })
+}
+
+async fn find_rate_limit(
+    client: &mut NamespacedKvClient,
+    group: &ConcurrencyGroup,
+) -> anyhow::Result<Option<RateLimitConfig>> {
+    let response = client.get(&RateLimitKey::new(group), None).await?;
+
+    let Some(kv) = response.kvs().first() else {
         return Ok(None);
+    };
+
+    Ok(Some(bson::from_slice(kv.value())?))
+}
+
+async fn find_token_bucket(
+    client: &mut NamespacedKvClient,
+    group: &ConcurrencyGroup,
+) -> anyhow::Result<Option<(TokenBucket, i64)>> {
+    let response = client.get(&TokenBucketKey::new(group), None).await?;
+
+    let Some(kv) = response.kvs().first() else {
+        return Ok(None);
+    };
+
+    Ok(Some((bson::from_slice(kv.value())?, kv.mod_revision())))
+}
+
+pub async fn list_rate_limits(client: &mut NamespacedKvClient) -> anyhow::Result<Vec<RateLimit>> {
+    let _timer = telemetry::time_etcd_operation("list_rate_limits");
+    let response = client
+        .get(
+            [RateLimitKey::PREFIX],
+            Some(GetOptions::default().with_prefix()),
+        )
+        .await?;
+
+    let mut limits = Vec::new();
+    for kv in response.kvs() {
+        limits.push(RateLimit {
+            group: RateLimitKey::group_from_key(kv.key())?,
+            config: Some(bson::from_slice(kv.value())?),
+        });
     }
 
-    Ok(Some(()))
+    Ok(limits)
+}
+
+pub async fn set_rate_limit(
+    client: &mut NamespacedKvClient,
+    group: ConcurrencyGroup,
+    config: Option<RateLimitConfig>,
+) -> anyhow::Result<RateLimit> {
+    let _timer = telemetry::time_etcd_operation("set_rate_limit");
+    // The token bucket is reset, so a changed burst size applies immediately.
+    let mut operations = vec![TxnOp::delete(&TokenBucketKey::new(&group), None)];
+    operations.push(match &config {
+        Some(config) => TxnOp::put(&RateLimitKey::new(&group), bson::to_vec(config)?, None),
+        None => TxnOp::delete(&RateLimitKey::new(&group), None),
+    });
+
+    client.txn(Txn::new().and_then(operations)).await?;
+
+    Ok(RateLimit { group, config })
 }
@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tokio::time::interval;
+
+use basti_types::{NodeStatus, TaskState};
+
+pub const TASKS_CREATED: &str = "basti_tasks_created_total";
+pub const TASKS_ACQUIRED: &str = "basti_tasks_acquired_total";
+pub const TASKS_STOLEN: &str = "basti_tasks_stolen_total";
+pub const TASKS_FINISHED: &str = "basti_tasks_finished_total";
+pub const TASKS_FAILED: &str = "basti_tasks_failed_total";
+pub const TASKS_REQUEUED: &str = "basti_tasks_requeued_total";
+pub const TASK_RUN_DURATION: &str = "basti_task_run_duration_seconds";
+pub const ETCD_OPERATION_DURATION: &str = "basti_etcd_operation_duration_seconds";
+pub const NODE_CPU: &str = "basti_node_cpu";
+pub const NODE_CPU_USED: &str = "basti_node_cpu_used";
+pub const NODE_MEMORY: &str = "basti_node_memory";
+pub const NODE_MEMORY_USED: &str = "basti_node_memory_used";
+pub const QUEUE_TASKS: &str = "basti_queue_tasks";
+
+const ETCD_OPERATION_BUCKETS: [f64; 10] =
+    [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+/// Interval at which samples of histograms are folded into their summaries.
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+const TASK_RUN_BUCKETS: [f64; 10] = [
+    1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 10800.0,
+];
+
+/// Installs the global metrics recorder, whose handle renders all metrics in
+/// the Prometheus text format.
+pub fn install() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(ETCD_OPERATION_DURATION.into()),
+            &ETCD_OPERATION_BUCKETS,
+        )?
+        .set_buckets_for_metric(Matcher::Full(TASK_RUN_DURATION.into()), &TASK_RUN_BUCKETS)?
+        .install_recorder()?;
+
+    describe_counter!(TASKS_CREATED, "Tasks created through this node's API");
+    describe_counter!(TASKS_ACQUIRED, "Tasks acquired by this node");
+    describe_counter!(
+        TASKS_STOLEN,
+        "Tasks acquired by another node between being read and acquired by this one"
+    );
+    describe_counter!(TASKS_FINISHED, "Tasks finished by this node");
+    describe_counter!(TASKS_FAILED, "Tasks failed by this node, by outcome");
+    describe_counter!(TASKS_REQUEUED, "Stale tasks requeued by this node");
+    describe_histogram!(
+        TASK_RUN_DURATION,
+        Unit::Seconds,
+        "Time from the first start of a task until it finished"
+    );
+    describe_histogram!(
+        ETCD_OPERATION_DURATION,
+        Unit::Seconds,
+        "Latency of operations on etcd, which may take several requests, by operation"
+    );
+    describe_gauge!(NODE_CPU, "CPU units of this node");
+    describe_gauge!(
+        NODE_CPU_USED,
+        "CPU units of this node held by running tasks"
+    );
+    describe_gauge!(NODE_MEMORY, "Memory units of this node");
+    describe_gauge!(
+        NODE_MEMORY_USED,
+        "Memory units of this node held by running tasks"
+    );
+    describe_gauge!(QUEUE_TASKS, "Tasks in the cluster, by state");
+
+    Ok(handle)
+}
+
+/// Keeps the metrics recorded by the installed recorder from growing without
+/// bound between scrapes.
+pub async fn run_upkeep(handle: PrometheusHandle) {
+    let mut upkeep = interval(UPKEEP_INTERVAL);
+    loop {
+        upkeep.tick().await;
+        handle.run_upkeep();
+    }
+}
+
+/// Records the latency of an operation on etcd once dropped, regardless of
+/// whether the operation succeeded.
+pub struct EtcdOperationTimer {
+    op: &'static str,
+    start: Instant,
+}
+
+impl Drop for EtcdOperationTimer {
+    fn drop(&mut self) {
+        histogram!(ETCD_OPERATION_DURATION, "op" => self.op).record(self.start.elapsed());
+    }
+}
+
+pub fn time_etcd_operation(op: &'static str) -> EtcdOperationTimer {
+    EtcdOperationTimer {
+        op,
+        start: Instant::now(),
+    }
+}
+
+/// Records the state of the node, which is kept by the worker pool and only
+/// read when metrics are scraped.
+pub fn record_node_status(status: NodeStatus) {
+    let total = status.capacity.total;
+    let used = total.saturating_sub(status.capacity.available);
+
+    gauge!(NODE_CPU).set(total.cpu);
+    gauge!(NODE_CPU_USED).set(used.cpu);
+    gauge!(NODE_MEMORY).set(total.memory);
+    gauge!(NODE_MEMORY_USED).set(used.memory);
+
+    counter!(TASKS_ACQUIRED).absolute(status.acquisitions.acquired);
+    counter!(TASKS_STOLEN).absolute(status.acquisitions.stolen);
+}
+
+pub fn record_queue_depth(state: TaskState, tasks: u64) {
+    gauge!(QUEUE_TASKS, "state" => state.to_string()).set(tasks as f64);
+}
+
+pub fn record_task_run(started_for: Duration) {
+    histogram!(TASK_RUN_DURATION).record(started_for);
+}
@@ -1,56 +1,264 @@
-use std::num::NonZeroUsize;
+use std::{
+    collections::HashSet,
+    future::pending,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use chrono::{TimeDelta, Utc};
-use etcd_client::KvClient;
-use futures::future;
+use chrono::{DateTime, TimeDelta, Utc};
+use metrics::counter;
 use tokio::{
-    sync::mpsc,
-    time::{sleep, Duration},
+    sync::Notify,
+    task::JoinSet,
+    time::{interval_at, sleep, Duration, Instant, MissedTickBehavior},
 };
+use tracing::{Instrument, Span};
+use uuid::Uuid;
 
-use basti_types::{Task, TaskState, WorkerName};
+use basti_types::{
+    AcquisitionStats, CreateTask, Labels, LogStream, NodeCapacity, PriorityKey, Resources, Task,
+    TaskOutcome, TaskState, TaskValue, WorkerName,
+};
 
 use crate::{
+    handler::{Handler, Reporter},
+    namespace::NamespacedKvClient,
     ops::{
-        acquire_task, find_task, finish_task, list_priorities, list_tasks, progress_task,
-        requeue_task,
+        acquire_task, acquire_tasks, admit_task, append_log, delete_done_task, fail_task,
+        find_pause_request, find_tasks, finish_task, list_done_tasks, list_priorities,
+        list_tasks_after, pause_task, progress_task, queue_status, requeue_task, Admission,
     },
-    shutdown_signal,
+    shutdown_signal, telemetry,
 };
 
-const WORK_TIMEOUT_DELTA: TimeDelta = TimeDelta::seconds(10);
-const WORK_FEEDBACK_INTERVAL: Duration = Duration::from_secs(5);
+const FIND_WORK_CANDIDATES: usize = 10;
+/// Maximum number of queued tasks looked at per poll for work, bounding the
+/// requests made when few of the queued tasks match the node's labels.
+const FIND_WORK_SCAN_LIMIT: usize = 200;
+const SWEEP_SCAN_LIMIT: i64 = 100;
+const RETENTION_SCAN_LIMIT: i64 = 100;
 
-pub async fn run(amount: NonZeroUsize, client: KvClient, name: WorkerName) {
-    let (work_sender, work_receiver) = async_channel::bounded(1);
-    let (work_request_sender, mut work_request_receiver) = mpsc::channel(amount.get());
+/// Timings of the worker subsystem, configurable per node.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerConfig {
+    /// Interval at which running tasks report progress, unless overridden by
+    /// the task.
+    pub heartbeat_interval: Duration,
+    /// Time without progress after which running tasks are requeued, unless
+    /// overridden by the task.
+    pub stale_timeout: Duration,
+    /// Interval at which to look for work and stale tasks while there's none.
+    pub poll_interval: Duration,
+    /// Time to wait after an error before trying again.
+    pub error_backoff: Duration,
+    /// Time after which done tasks and their output are deleted.
+    pub retention: Duration,
+}
 
-    let worker_handles = (0..amount.get()).map(|_| {
-        let task_receiver: async_channel::Receiver<(Task, i64)> = work_receiver.clone();
-        let work_request_sender: mpsc::Sender<()> = work_request_sender.clone();
-        let mut client = client.clone();
-        async move {
-            loop {
-                work_request_sender.send(()).await.unwrap();
-                let (task, revision) = task_receiver.recv().await.unwrap();
-                if work_on_task(&mut client, task, revision).await.is_err() {
-                    sleep(Duration::from_secs(5)).await;
-                }
-            }
+impl WorkerConfig {
+    fn heartbeat_interval(&self, task: &Task) -> Duration {
+        task.value
+            .heartbeat_interval
+            .unwrap_or(self.heartbeat_interval)
+    }
+
+    fn stale_timeout(&self, task: &Task) -> Duration {
+        task.value.stale_timeout.unwrap_or(self.stale_timeout)
+    }
+
+    /// Whether the node's heartbeat interval is shorter than its stale timeout.
+    pub fn is_valid(&self) -> bool {
+        valid_timeouts(self.heartbeat_interval, self.stale_timeout)
+    }
+
+    /// Fills in the node's heartbeat interval and stale timeout for the ones
+    /// a task doesn't override, so every node uses the same pair for it, and
+    /// checks that the heartbeat interval is shorter than the stale timeout
+    /// and the task's timeout isn't zero.
+    pub fn fill_task_timeouts(&self, payload: &mut CreateTask) -> bool {
+        let heartbeat_interval = *payload
+            .heartbeat_interval
+            .get_or_insert(self.heartbeat_interval);
+        let stale_timeout = *payload.stale_timeout.get_or_insert(self.stale_timeout);
+
+        valid_timeouts(heartbeat_interval, stale_timeout)
+            && payload.timeout.is_none_or(|timeout| !timeout.is_zero())
+    }
+}
+
+/// Resources of the node, shared between the worker pool and the API so the
+/// pool can be resized at runtime.
+pub struct Capacity {
+    state: Mutex<CapacityState>,
+    changed: Notify,
+}
+
+struct CapacityState {
+    total: Resources,
+    used: Resources,
+}
+
+impl Capacity {
+    pub fn new(total: Resources) -> Self {
+        Self {
+            state: Mutex::new(CapacityState {
+                total,
+                used: Resources::ZERO,
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    pub fn get(&self) -> NodeCapacity {
+        let state = self.state.lock().unwrap();
+        NodeCapacity {
+            total: state.total,
+            available: state.total.saturating_sub(state.used),
+        }
+    }
+
+    /// Sets the CPU units of the node. When shrinking, running tasks keep
+    /// their resources until they're done, no new tasks start until then.
+    pub fn scale(&self, workers: u32) -> NodeCapacity {
+        self.state.lock().unwrap().total.cpu = workers;
+        self.changed.notify_one();
+        self.get()
+    }
+
+    /// Takes resources for a task until the returned reservation is dropped,
+    /// which also happens if working on the task panics.
+    fn take(self: &Arc<Self>, request: Resources) -> Reservation {
+        let mut state = self.state.lock().unwrap();
+        state.used = state.used.saturating_add(request);
+
+        Reservation {
+            capacity: self.clone(),
+            request,
+        }
+    }
+}
+
+/// Resources of a node held by a task.
+struct Reservation {
+    capacity: Arc<Capacity>,
+    request: Resources,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.capacity.state.lock().unwrap();
+        state.used = state.used.saturating_sub(self.request);
+        self.capacity.changed.notify_one();
+    }
+}
+
+/// Counts outcomes of task acquisitions, to observe contention between nodes.
+#[derive(Default)]
+pub struct AcquisitionCounters {
+    acquired: AtomicU64,
+    stolen: AtomicU64,
+    batch_conflicts: AtomicU64,
+}
+
+impl AcquisitionCounters {
+    pub fn get(&self) -> AcquisitionStats {
+        AcquisitionStats {
+            acquired: self.acquired.load(Ordering::Relaxed),
+            stolen: self.stolen.load(Ordering::Relaxed),
+            batch_conflicts: self.batch_conflicts.load(Ordering::Relaxed),
         }
-    });
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
 
+fn valid_timeouts(heartbeat_interval: Duration, stale_timeout: Duration) -> bool {
+    !heartbeat_interval.is_zero() && heartbeat_interval < stale_timeout
+}
+
+/// Span of the lifecycle events of a task, identifying the task and which
+/// attempt at running it they belong to. Spans carrying identifiers are
+/// enabled at the error level, so they aren't filtered out by a log level
+/// which keeps the warnings and errors within them.
+fn task_span(task: &Task) -> Span {
+    tracing::error_span!(
+        "task",
+        task_id = %task.key.id,
+        attempt = task.value.attempts,
+        slot = task.value.concurrency_slot,
+    )
+}
+
+/// Span of the events of acquiring a queued task, which belong to the attempt
+/// the acquisition starts. The slot is recorded once the task is acquired.
+fn acquisition_span(task: &Task) -> Span {
+    tracing::error_span!(
+        "task",
+        task_id = %task.key.id,
+        attempt = task.value.attempts + 1,
+        slot = tracing::field::Empty,
+    )
+}
+
+/// Records an acquired task within the span of its acquisition.
+fn record_acquired(span: &Span, task: &Task, counters: &AcquisitionCounters) {
+    if let Some(slot) = task.value.concurrency_slot {
+        span.record("slot", slot);
+    }
+    span.in_scope(|| tracing::info!(event = "acquired"));
+    AcquisitionCounters::count(&counters.acquired);
+}
+
+#[tracing::instrument(level = "error", skip_all, fields(worker = %name))]
+pub async fn run(
+    capacity: Arc<Capacity>,
+    counters: Arc<AcquisitionCounters>,
+    client: NamespacedKvClient,
+    name: WorkerName,
+    labels: Labels,
+    config: WorkerConfig,
+    handler: Arc<dyn Handler>,
+) {
     let find_work_handle = {
         let mut client = client.clone();
         async move {
-            work_request_receiver.recv().await.unwrap();
+            let mut workers = JoinSet::new();
             loop {
-                match find_work(&mut client, name.clone()).await {
-                    Err(_) => sleep(Duration::from_secs(5)).await,
-                    Ok(None) => sleep(Duration::from_millis(500)).await,
-                    Ok(Some(work)) => {
-                        work_sender.send(work).await.unwrap();
-                        work_request_receiver.recv().await.unwrap();
+                while workers.try_join_next().is_some() {}
+
+                let available = capacity.get().available;
+                if available == Resources::ZERO {
+                    capacity.changed.notified().await;
+                    continue;
+                }
+
+                match find_work(&mut client, name.clone(), &labels, available, &counters).await {
+                    Err(_) => sleep(config.error_backoff).await,
+                    Ok(work) if work.is_empty() => sleep(config.poll_interval).await,
+                    Ok(work) => {
+                        for (task, revision) in work {
+                            let reservation = capacity.take(task.value.resources);
+
+                            let span = task_span(&task);
+                            let handler = handler.clone();
+                            let mut client = client.clone();
+                            workers.spawn(
+                                async move {
+                                    if work_on_task(&mut client, &*handler, task, revision, &config)
+                                        .await
+                                        .is_err()
+                                    {
+                                        sleep(config.error_backoff).await;
+                                    }
+                                    drop(reservation);
+                                }
+                                .instrument(span),
+                            );
+                        }
                     }
                 };
             }
@@ -60,22 +268,17 @@ pub async fn run(amount: NonZeroUsize, client: KvClient, name: WorkerName) {
     let requeue_tasks_handle = {
         let mut client = client.clone();
         async move {
+            let mut cursors = SweepCursors::default();
             loop {
-                match requeue_tasks(&mut client).await {
-                    Err(_) => sleep(Duration::from_secs(5)).await,
-                    Ok(()) => sleep(Duration::from_millis(500)).await,
+                match requeue_tasks(&mut client, &config, &mut cursors).await {
+                    Err(_) => sleep(config.error_backoff).await,
+                    Ok(()) => sleep(config.poll_interval).await,
                 };
             }
         }
     };
 
-    let joined_handles = async {
-        tokio::join!(
-            future::join_all(worker_handles),
-            find_work_handle,
-            requeue_tasks_handle
-        )
-    };
+    let joined_handles = async { tokio::join!(find_work_handle, requeue_tasks_handle) };
 
     tokio::select! {
         () = shutdown_signal() => {}
@@ -85,44 +288,88 @@ pub async fn run(amount: NonZeroUsize, client: KvClient, name: WorkerName) {
 
 #[tracing::instrument(skip_all, err(Display))]
 async fn work_on_task(
-    client: &mut KvClient,
+    client: &mut NamespacedKvClient,
+    handler: &dyn Handler,
     mut task: Task,
     mut revision: i64,
+    config: &WorkerConfig,
 ) -> anyhow::Result<()> {
     let task_id = task.key.id;
+    let heartbeat_interval = config.heartbeat_interval(&task);
+    let timeout_at = task.value.timeout_at();
+    let reporter = Reporter::default();
+    let mut run = handler.run(task.clone(), reporter.clone());
 
-    while !task.value.remaining.is_zero() {
-        let work_duration = if task.value.remaining >= WORK_FEEDBACK_INTERVAL {
-            WORK_FEEDBACK_INTERVAL
-        } else {
-            task.value.remaining
-        };
-
-        tracing::info!(
-            id = %task_id,
-            event = "working",
-            amount = format!(
-                "{}.{:03}s",
-                work_duration.as_secs(),
-                work_duration.subsec_millis()
-            ),
-        );
+    let timeout = async {
+        match timeout_at {
+            Some(timeout_at) => sleep((timeout_at - Utc::now()).to_std().unwrap_or_default()).await,
+            None => pending().await,
+        }
+    };
+    tokio::pin!(timeout);
 
-        sleep(work_duration).await;
+    // Heartbeats are sent on the worker's own timer, however long the handler
+    // goes without reporting.
+    let mut heartbeat = interval_at(Instant::now() + heartbeat_interval, heartbeat_interval);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-        (task, revision) =
-            if let Some(update) = progress_task(client, task, revision, work_duration).await? {
-                update
-            } else {
-                tracing::warn!(id = %task_id, event = "stolen");
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            () = &mut timeout => {
+                reporter.write(LogStream::Stderr, "timed out\n");
+                write_logs(client, task_id, &reporter).await;
+                match fail_task(client, task, revision, TaskOutcome::TimedOut).await? {
+                    Some(_) => tracing::warn!(event = "timed_out"),
+                    None => tracing::warn!(event = "stolen"),
+                }
                 return Ok(());
-            };
+            }
+            _ = heartbeat.tick() => {
+                let Some(update) = send_heartbeat(client, task, revision, &reporter).await? else {
+                    tracing::warn!(event = "stolen");
+                    return Ok(());
+                };
+                (task, revision) = update;
+
+                if find_pause_request(client, task_id).await? {
+                    if pause_task(client, task, revision).await?.is_some() {
+                        tracing::info!(event = "paused");
+                    } else {
+                        tracing::warn!(event = "stolen");
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            reporter.write(LogStream::Stderr, format!("{err:#}\n"));
+            write_logs(client, task_id, &reporter).await;
+            let outcome = TaskOutcome::HandlerFailed(format!("{err:#}"));
+            match fail_task(client, task, revision, outcome).await? {
+                Some(_) => tracing::warn!(event = "handler_failed", error = %err),
+                None => tracing::warn!(event = "stolen"),
+            }
+            return Ok(());
+        }
+    };
+    write_logs(client, task_id, &reporter).await;
+
+    if serde_json::to_vec(&result)?.len() > TaskValue::MAX_RESULT_SIZE {
+        match fail_task(client, task, revision, TaskOutcome::ResultTooLarge).await? {
+            Some(_) => tracing::warn!(event = "result_too_large"),
+            None => tracing::warn!(event = "stolen"),
+        }
+        return Ok(());
     }
 
-    if let Some(()) = finish_task(client, &task.key, revision).await? {
+    if let Some((task, _)) = finish_task(client, task, revision, Some(result)).await? {
         let time_taken = (Utc::now() - task.value.created_at).to_std()?;
         tracing::info!(
-            id = %task_id,
             event = "finished",
             total = format!(
                 "{}.{:03}s",
@@ -131,54 +378,363 @@ async fn work_on_task(
             ),
         );
     } else {
-        tracing::warn!(id = %task_id, event = "stolen");
+        tracing::warn!(event = "stolen");
     }
 
     Ok(())
 }
 
-#[tracing::instrument(skip_all, err(Display))]
-async fn find_work(client: &mut KvClient, name: WorkerName) -> anyhow::Result<Option<(Task, i64)>> {
-    let priorities = list_priorities(client, 10).await?;
-
-    for priority in priorities {
-        let Some((task, revision)) = find_task(client, priority.id, &[TaskState::Queued]).await?
-        else {
-            continue;
-        };
+/// Stores the progress and output a task's handler reported since the last
+/// heartbeat, unless the task was taken over by another node.
+async fn send_heartbeat(
+    client: &mut NamespacedKvClient,
+    task: Task,
+    revision: i64,
+    reporter: &Reporter,
+) -> anyhow::Result<Option<(Task, i64)>> {
+    let mut report = reporter.take_progress();
 
-        if let Some((task, revision)) = acquire_task(client, task, revision, name.clone()).await? {
-            tracing::info!(id = %task.key.id, event = "acquired");
-            return Ok(Some((task, revision)));
+    if let Some(checkpoint) = &report.checkpoint {
+        if serde_json::to_vec(checkpoint)?.len() > TaskValue::MAX_CHECKPOINT_SIZE {
+            tracing::warn!(event = "checkpoint_too_large");
+            report.checkpoint = None;
         }
+    }
 
+    let update = progress_task(client, task, revision, report).await?;
+    if let Some((task, _)) = &update {
         tracing::info!(
-            id = %priority.id,
-            event = "stolen"
+            event = "heartbeat",
+            progress = format!("{:.0}%", task.value.progress.unwrap_or_default() * 100.0),
         );
+        write_logs(client, task.key.id, reporter).await;
     }
 
-    Ok(None)
+    Ok(update)
 }
 
+/// Stores the output a task's handler wrote since the last call. Failing to
+/// do so doesn't fail the task.
+async fn write_logs(client: &mut NamespacedKvClient, id: Uuid, reporter: &Reporter) {
+    for (stream, data) in reporter.take_output() {
+        if let Err(err) = append_log(client, id, stream, &data).await {
+            tracing::warn!(event = "log_dropped", error = %err);
+        }
+    }
+}
+
+/// Acquires as many of the next queued tasks as fit into the available
+/// resources.
 #[tracing::instrument(skip_all, err(Display))]
-async fn requeue_tasks(client: &mut KvClient) -> anyhow::Result<()> {
-    let tasks = list_tasks(client, Some(TaskState::Running), 10).await?;
+async fn find_work(
+    client: &mut NamespacedKvClient,
+    name: WorkerName,
+    labels: &Labels,
+    mut available: Resources,
+    counters: &AcquisitionCounters,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    if queue_status(client).await?.paused {
+        return Ok(Vec::new());
+    }
+
+    let mut scan = CandidateScan::default();
+    // Groups at their concurrency or rate limit, whose tasks are skipped for
+    // the rest of the poll.
+    let mut saturated = HashSet::new();
+    let mut acquired = Vec::new();
+    let mut batch = Vec::new();
+    let now = Utc::now();
+
+    // Tasks which can't be started right now don't count towards the
+    // candidates, so the next ones are looked at instead of stalling behind
+    // them until the scan limit is reached.
+    while acquired.len() + batch.len() < FIND_WORK_CANDIDATES {
+        let candidates = find_candidates(client, labels, &mut scan).await?;
+        if candidates.is_empty() {
+            break;
+        }
+
+        let mut tasks = find_tasks(client, &candidates, TaskState::Queued).await?;
+
+        // Nodes looking for work at the same time all see the same
+        // candidates. Starting at a random one spreads them over the
+        // candidates instead of having them race for the first, at the cost
+        // of strict priority order within the candidates.
+        if !tasks.is_empty() {
+            let offset = fastrand::usize(..tasks.len());
+            tasks.rotate_left(offset);
+        }
+
+        for (task, revision) in tasks {
+            if task.value.past_deadline(now) {
+                let span = task_span(&task);
+                fail_overdue_task(client, task, revision)
+                    .instrument(span)
+                    .await?;
+                continue;
+            }
+
+            let span = acquisition_span(&task);
+
+            let request = task.value.resources;
+            if !available.fits(&request) {
+                span.in_scope(|| tracing::debug!(event = "insufficient_resources"));
+                continue;
+            }
+
+            // Tasks outside of concurrency groups don't need admission and
+            // are acquired together below.
+            let Some(group) = task.value.concurrency_group.clone() else {
+                available = available.saturating_sub(request);
+                batch.push((task, revision, span));
+                continue;
+            };
+
+            if saturated.contains(&group) {
+                continue;
+            }
+
+            let Some(admission) = admit_task(client, &task).await? else {
+                span.in_scope(|| tracing::debug!(event = "throttled"));
+                saturated.insert(group);
+                continue;
+            };
+
+            if let Some(work) =
+                acquire_task(client, task, revision, name.clone(), admission).await?
+            {
+                record_acquired(&span, &work.0, counters);
+                available = available.saturating_sub(request);
+                acquired.push(work);
+            } else {
+                span.in_scope(|| tracing::debug!(event = "stolen"));
+                AcquisitionCounters::count(&counters.stolen);
+            }
+        }
+    }
+
+    acquired.extend(acquire_batch(client, name, batch, counters).await?);
+
+    Ok(acquired)
+}
+
+/// Position of a poll for work in the priority index, so it can keep looking
+/// past tasks which can't be started right now.
+#[derive(Default)]
+struct CandidateScan {
+    after: Option<PriorityKey>,
+    scanned: usize,
+}
+
+/// Lists the IDs of the next queued tasks whose selector matches the labels.
+/// Tasks whose selector doesn't match are skipped without counting towards
+/// the candidates, so they can't starve matching tasks further down, up to
+/// [`FIND_WORK_SCAN_LIMIT`] tasks per poll.
+async fn find_candidates(
+    client: &mut NamespacedKvClient,
+    labels: &Labels,
+    scan: &mut CandidateScan,
+) -> anyhow::Result<Vec<Uuid>> {
+    let mut candidates = Vec::new();
+
+    while candidates.len() < FIND_WORK_CANDIDATES && scan.scanned < FIND_WORK_SCAN_LIMIT {
+        let priorities = list_priorities(client, scan.after.as_ref(), 10).await?;
+        if priorities.is_empty() {
+            break;
+        }
+
+        for (priority, selector) in priorities {
+            if candidates.len() == FIND_WORK_CANDIDATES {
+                break;
+            }
+
+            scan.after = Some(priority);
+            scan.scanned += 1;
+
+            if labels.matches(&selector) {
+                candidates.push(priority.id);
+            }
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Acquires tasks in a single transaction, falling back to acquiring them one
+/// by one if any of them was stolen in the meantime.
+async fn acquire_batch(
+    client: &mut NamespacedKvClient,
+    name: WorkerName,
+    batch: Vec<(Task, i64, Span)>,
+    counters: &AcquisitionCounters,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    if batch.len() > 1 {
+        let tasks = batch
+            .iter()
+            .map(|(task, revision, _)| (task.clone(), *revision))
+            .collect();
+        if let Some(acquired) = acquire_tasks(client, tasks, name.clone()).await? {
+            for ((task, _), (_, _, span)) in acquired.iter().zip(&batch) {
+                record_acquired(span, task, counters);
+            }
+            return Ok(acquired);
+        }
+
+        tracing::debug!(event = "batch_conflict");
+        AcquisitionCounters::count(&counters.batch_conflicts);
+    }
+
+    let mut acquired = Vec::new();
+    for (task, revision, span) in batch {
+        match acquire_task(client, task, revision, name.clone(), Admission::default()).await? {
+            Some(work) => {
+                record_acquired(&span, &work.0, counters);
+                acquired.push(work);
+            }
+            None => {
+                span.in_scope(|| tracing::debug!(event = "stolen"));
+                AcquisitionCounters::count(&counters.stolen);
+            }
+        }
+    }
+
+    Ok(acquired)
+}
+
+/// Keys after which the sweeps of running and queued tasks continue.
+#[derive(Debug, Default)]
+struct SweepCursors {
+    running: Option<Vec<u8>>,
+    queued: Option<Vec<u8>>,
+}
+
+/// Lists the tasks of a state after the ones of the previous pass of a sweep,
+/// starting over once the end is reached.
+async fn sweep_page(
+    client: &mut NamespacedKvClient,
+    state: TaskState,
+    cursor: &mut Option<Vec<u8>>,
+) -> anyhow::Result<Vec<(Task, i64)>> {
+    let tasks = list_tasks_after(client, state, cursor.as_deref(), SWEEP_SCAN_LIMIT).await?;
+    *cursor = match tasks.last() {
+        Some((task, _)) if tasks.len() as i64 == SWEEP_SCAN_LIMIT => Some(Vec::from(&task.key)),
+        _ => None,
+    };
+
+    Ok(tasks)
+}
+
+#[tracing::instrument(skip_all, err(Display))]
+async fn requeue_tasks(
+    client: &mut NamespacedKvClient,
+    config: &WorkerConfig,
+    cursors: &mut SweepCursors,
+) -> anyhow::Result<()> {
+    let tasks = sweep_page(client, TaskState::Running, &mut cursors.running).await?;
     let now = Utc::now();
 
     for (task, revision) in tasks {
-        if now - task.value.updated_at < WORK_TIMEOUT_DELTA {
+        let since_update = (now - task.value.updated_at).to_std().unwrap_or_default();
+        if since_update < config.stale_timeout(&task) {
             continue;
         }
 
-        let task_id = task.key.id;
-        match requeue_task(client, task, revision).await? {
-            Some(_) => tracing::info!(id = %task_id, event = "requeued"),
-            None => {
-                tracing::info!(id = %task_id, event = "stolen");
+        let span = task_span(&task);
+        recover_stale_task(client, task, revision, now)
+            .instrument(span)
+            .await?;
+    }
+
+    let tasks = sweep_page(client, TaskState::Queued, &mut cursors.queued).await?;
+    for (task, revision) in tasks {
+        if task.value.past_deadline(now) {
+            let span = task_span(&task);
+            fail_overdue_task(client, task, revision)
+                .instrument(span)
+                .await?;
+        }
+    }
+
+    delete_expired_tasks(client, config, now).await
+}
+
+/// Deletes tasks which have been done for longer than the retention period.
+async fn delete_expired_tasks(
+    client: &mut NamespacedKvClient,
+    config: &WorkerConfig,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let Some(done_before) = TimeDelta::from_std(config.retention)
+        .ok()
+        .and_then(|retention| now.checked_sub_signed(retention))
+    else {
+        return Ok(());
+    };
+
+    for state in [TaskState::Failed, TaskState::Finished] {
+        for (task, revision) in list_done_tasks(client, state, RETENTION_SCAN_LIMIT).await? {
+            if task.value.updated_at >= done_before {
+                break;
+            }
+
+            if delete_done_task(client, &task, revision).await? {
+                task_span(&task).in_scope(|| tracing::debug!(event = "expired"));
             }
         }
     }
 
     Ok(())
 }
+
+/// Fails, pauses or requeues a running task whose worker stopped reporting
+/// progress.
+async fn recover_stale_task(
+    client: &mut NamespacedKvClient,
+    task: Task,
+    revision: i64,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    // Stale tasks past their timeout aren't worth another attempt.
+    if task
+        .value
+        .timeout_at()
+        .is_some_and(|timeout_at| timeout_at <= now)
+    {
+        match fail_task(client, task, revision, TaskOutcome::TimedOut).await? {
+            Some(_) => tracing::warn!(event = "timed_out"),
+            None => tracing::info!(event = "stolen"),
+        }
+        return Ok(());
+    }
+
+    if find_pause_request(client, task.key.id).await? {
+        match pause_task(client, task, revision).await? {
+            Some(_) => tracing::info!(event = "paused"),
+            None => tracing::info!(event = "stolen"),
+        }
+        return Ok(());
+    }
+
+    match requeue_task(client, task, revision).await? {
+        Some(_) => {
+            tracing::info!(event = "requeued");
+            counter!(telemetry::TASKS_REQUEUED).increment(1);
+        }
+        None => tracing::info!(event = "stolen"),
+    }
+
+    Ok(())
+}
+
+async fn fail_overdue_task(
+    client: &mut NamespacedKvClient,
+    task: Task,
+    revision: i64,
+) -> anyhow::Result<()> {
+    match fail_task(client, task, revision, TaskOutcome::DeadlineExceeded).await? {
+        Some(_) => tracing::warn!(event = "deadline_exceeded"),
+        None => tracing::info!(event = "stolen"),
+    }
+
+    Ok(())
+}
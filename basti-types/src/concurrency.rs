@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::ConcurrencyGroup;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SetConcurrencyLimit {
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcurrencyLimit {
+    pub group: ConcurrencyGroup,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    pub running: u32,
+}
+
+/// Holds the maximum number of concurrently running tasks of a group.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimitKey<'a> {
+    pub group: &'a ConcurrencyGroup,
+}
+
+impl<'a> ConcurrencyLimitKey<'a> {
+    pub const PREFIX: u8 = b'c';
+
+    #[must_use]
+    pub fn new(group: &'a ConcurrencyGroup) -> Self {
+        Self { group }
+    }
+
+    pub fn group_from_key(key: &[u8]) -> anyhow::Result<ConcurrencyGroup> {
+        let [Self::PREFIX, group @ ..] = key else {
+            bail!("unexpected prefix byte");
+        };
+
+        ConcurrencyGroup::from_str(std::str::from_utf8(group)?)
+    }
+}
+
+impl From<&ConcurrencyLimitKey<'_>> for Vec<u8> {
+    fn from(value: &ConcurrencyLimitKey<'_>) -> Self {
+        let mut result = vec![ConcurrencyLimitKey::PREFIX];
+        result.extend_from_slice(value.group.inner().as_bytes());
+        result
+    }
+}
+
+/// One of the numbered slots of a concurrency group, held by a running task.
+/// Slots are claimed together with acquiring a task, so a group never has
+/// more running tasks than free slots below its limit.
+#[derive(Debug, Clone)]
+pub struct ConcurrencySlotKey<'a> {
+    pub group: &'a ConcurrencyGroup,
+    pub slot: u32,
+}
+
+impl<'a> ConcurrencySlotKey<'a> {
+    pub const PREFIX: u8 = b's';
+
+    #[must_use]
+    pub fn new(group: &'a ConcurrencyGroup, slot: u32) -> Self {
+        Self { group, slot }
+    }
+
+    /// Common prefix of all slot keys of a group.
+    #[must_use]
+    pub fn group_prefix(group: &ConcurrencyGroup) -> Vec<u8> {
+        let mut result = vec![Self::PREFIX];
+        result.extend_from_slice(group.inner().as_bytes());
+        result.push(b'/');
+        result
+    }
+
+    pub fn slot_from_key(group: &ConcurrencyGroup, key: &[u8]) -> anyhow::Result<u32> {
+        let Some(slot) = key.strip_prefix(Self::group_prefix(group).as_slice()) else {
+            bail!("unexpected slot key prefix");
+        };
+
+        Ok(u32::from_be_bytes(slot.try_into()?))
+    }
+}
+
+impl From<&ConcurrencySlotKey<'_>> for Vec<u8> {
+    fn from(value: &ConcurrencySlotKey<'_>) -> Self {
+        let mut result = ConcurrencySlotKey::group_prefix(value.group);
+        result.extend_from_slice(&value.slot.to_be_bytes());
+        result
+    }
+}
@@ -0,0 +1,57 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// Key-value pairs describing a worker or task, matched against selectors.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Labels(pub BTreeMap<String, String>);
+
+impl Labels {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether these labels contain every pair of the given selector.
+    #[must_use]
+    pub fn matches(&self, selector: &Labels) -> bool {
+        selector
+            .0
+            .iter()
+            .all(|(key, value)| self.0.get(key) == Some(value))
+    }
+}
+
+impl FromStr for Labels {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut labels = BTreeMap::new();
+
+        for pair in s.split(',').filter(|pair| !pair.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                bail!("label `{pair}` is not of the form key=value")
+            };
+
+            if key.is_empty() {
+                bail!("label key is empty")
+            }
+
+            labels.insert(key.to_string(), value.to_string());
+        }
+
+        Ok(Self(labels))
+    }
+}
+
+impl Display for Labels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}", pairs.join(","))
+    }
+}
@@ -1,4 +1,15 @@
+mod concurrency;
+mod labels;
+mod list;
+mod logs;
 mod name;
+mod node;
+mod queue;
+mod rate_limit;
+mod resources;
 mod task;
 
-pub use crate::{name::*, task::*};
+pub use crate::{
+    concurrency::*, labels::*, list::*, logs::*, name::*, node::*, queue::*, rate_limit::*,
+    resources::*, task::*,
+};
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::{Task, TaskPriority, TaskState, WorkerName};
+
+/// Query of a listing of tasks. All filters are optional and combined.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListTasks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<TaskState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// Comma-separated key=value labels the tasks need to have.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assignee: Option<WorkerName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_priority: Option<TaskPriority>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority: Option<TaskPriority>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// Order to list tasks in. Orders other than by state require a state
+    /// filter.
+    #[serde(default)]
+    pub sort: TaskSort,
+    /// Continuation cursor returned with the previous page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+/// Order of listed tasks. Ties are broken by task ID.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, Display,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    /// Running tasks first, then queued, paused, failed and finished ones.
+    #[default]
+    State,
+    /// Highest priority first.
+    Priority,
+    /// Oldest first.
+    CreatedAt,
+    /// Least recently updated first.
+    UpdatedAt,
+}
+
+/// Page of listed tasks. The API responds with the tasks as the body and the
+/// cursor in a header, so clients reading only the body still get a list.
+#[derive(Debug, Clone)]
+pub struct TaskPage {
+    pub tasks: Vec<Task>,
+    /// Cursor to fetch the next page with, if there may be more tasks.
+    pub cursor: Option<String>,
+}
+
+impl TaskPage {
+    /// Response header carrying the cursor of the next page.
+    pub const CURSOR_HEADER: &'static str = "next-cursor";
+}
@@ -0,0 +1,85 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of output written by a task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogChunk {
+    pub seq: u32,
+    pub stream: LogStream,
+    pub data: String,
+    pub written_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskLogs {
+    pub chunks: Vec<LogChunk>,
+    /// Whether the task won't write any more output.
+    pub done: bool,
+}
+
+/// Holds a single chunk of a task's output, ordered by sequence number.
+#[derive(Debug, Clone, Copy)]
+pub struct LogChunkKey {
+    pub id: Uuid,
+    pub seq: u32,
+}
+
+impl LogChunkKey {
+    pub const PREFIX: u8 = b'o';
+    /// Maximum number of bytes stored per chunk, longer output is split over
+    /// several chunks.
+    pub const MAX_CHUNK_SIZE: usize = 4096;
+    /// Maximum number of chunks stored per task. The last one notes that the
+    /// output was truncated, later output is dropped.
+    pub const MAX_CHUNKS: u32 = 1024;
+
+    #[must_use]
+    pub fn new(id: Uuid, seq: u32) -> Self {
+        Self { id, seq }
+    }
+
+    /// Prefix of the keys of all chunks of a task.
+    #[must_use]
+    pub fn task_prefix(id: Uuid) -> Vec<u8> {
+        let mut result = vec![Self::PREFIX];
+        result.extend_from_slice(id.as_bytes());
+        result
+    }
+}
+
+impl From<&LogChunkKey> for Vec<u8> {
+    fn from(value: &LogChunkKey) -> Self {
+        let mut result = LogChunkKey::task_prefix(value.id);
+        result.extend_from_slice(&value.seq.to_be_bytes());
+        result
+    }
+}
+
+impl TryFrom<&[u8]> for LogChunkKey {
+    type Error = anyhow::Error;
+    fn try_from(value: &[u8]) -> anyhow::Result<Self> {
+        let [Self::PREFIX, rest @ ..] = value else {
+            bail!("unexpected prefix byte");
+        };
+
+        let Some((id, seq)) = rest.split_at_checked(16) else {
+            bail!("log chunk key is too short");
+        };
+
+        Ok(Self::new(
+            Uuid::from_slice(id)?,
+            u32::from_be_bytes(seq.try_into()?),
+        ))
+    }
+}
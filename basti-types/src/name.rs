@@ -3,7 +3,7 @@ use std::{fmt::Display, str::FromStr};
 use anyhow::bail;
 use serde::{Deserialize, Serialize};
 
-fn validate_worker_name(s: &str) -> anyhow::Result<()> {
+fn validate_name(s: &str) -> anyhow::Result<()> {
     if s.is_empty() {
         bail!("name is empty")
     }
@@ -45,7 +45,7 @@ impl WorkerName {
 impl FromStr for WorkerName {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        validate_worker_name(s).map(|()| Self(s.to_string()))
+        validate_name(s).map(|()| Self(s.to_string()))
     }
 }
 
@@ -75,3 +75,80 @@ impl<'de> Deserialize<'de> for WorkerName {
             .map_err(serde::de::Error::custom)
     }
 }
+
+/// Name of a group of tasks sharing a cluster-wide concurrency limit.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConcurrencyGroup(String);
+
+impl ConcurrencyGroup {
+    #[must_use]
+    pub fn inner(&self) -> &String {
+        &self.0
+    }
+}
+
+impl FromStr for ConcurrencyGroup {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_name(s).map(|()| Self(s.to_string()))
+    }
+}
+
+impl Display for ConcurrencyGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for ConcurrencyGroup {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConcurrencyGroup {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Self::from_str(name.as_ref()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace(Option<String>);
+
+impl Namespace {
+    /// Key prefix under which all keys of this namespace are stored. The
+    /// default namespace stays at the root of the keyspace, where none of its
+    /// keys start with `n`, so they can't collide with the keys of named
+    /// namespaces under `ns/`.
+    #[must_use]
+    pub fn prefix(&self) -> Vec<u8> {
+        match &self.0 {
+            None => Vec::new(),
+            Some(name) => format!("ns/{name}/").into_bytes(),
+        }
+    }
+}
+
+impl FromStr for Namespace {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self(None));
+        }
+
+        validate_name(s).map(|()| Self(Some(s.to_string())))
+    }
+}
+
+impl Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.as_deref().unwrap_or_default())
+    }
+}
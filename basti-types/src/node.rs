@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Resources;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ScaleWorkers {
+    pub workers: u32,
+}
+
+/// Resources of a single node and the part of them not held by running tasks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NodeCapacity {
+    pub total: Resources,
+    pub available: Resources,
+}
+
+/// Outcomes of task acquisitions by a node since it started.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AcquisitionStats {
+    pub acquired: u64,
+    /// Tasks acquired by another node between being read and acquired.
+    pub stolen: u64,
+    /// Batch acquisitions which failed because one of their tasks was stolen.
+    pub batch_conflicts: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NodeStatus {
+    pub capacity: NodeCapacity,
+    pub acquisitions: AcquisitionStats,
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ConcurrencyLimit, TaskPriority, TaskState};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct QueueStatus {
+    pub paused: bool,
+}
+
+/// Cluster-wide flag which is present while the queue is paused.
+#[derive(Debug, Clone, Copy)]
+pub struct QueuePausedKey;
+
+impl QueuePausedKey {
+    pub const PREFIX: u8 = b'g';
+}
+
+impl From<&QueuePausedKey> for Vec<u8> {
+    fn from(_: &QueuePausedKey) -> Self {
+        vec![QueuePausedKey::PREFIX]
+    }
+}
+
+/// Overview of the tasks in the cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub paused: bool,
+    pub states: Vec<StateCount>,
+    /// Queued tasks per priority, highest priority first.
+    pub priorities: Vec<PriorityCount>,
+    /// Running tasks per concurrency group with a limit.
+    pub groups: Vec<ConcurrencyLimit>,
+    /// Time the task which has been queued the longest has been waiting since
+    /// it was last queued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oldest_queued: Option<Duration>,
+    /// Tasks done within recent windows of time, shortest window first.
+    /// Counted from the done tasks themselves, so it takes longer to compute
+    /// the more tasks were done within the longest window.
+    pub throughput: Vec<Throughput>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StateCount {
+    pub state: TaskState,
+    pub tasks: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PriorityCount {
+    pub priority: TaskPriority,
+    pub tasks: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Throughput {
+    pub window: Duration,
+    pub finished: u64,
+    pub failed: u64,
+}
@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::ConcurrencyGroup;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// Number of task starts replenished per minute.
+    pub per_minute: u32,
+    /// Number of task starts that may happen in a burst.
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SetRateLimit {
+    pub per_minute: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+}
+
+impl From<SetRateLimit> for RateLimitConfig {
+    fn from(value: SetRateLimit) -> Self {
+        Self {
+            per_minute: value.per_minute,
+            burst: value.burst.unwrap_or(1).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub group: ConcurrencyGroup,
+    #[serde(flatten)]
+    pub config: Option<RateLimitConfig>,
+}
+
+/// Token bucket shared by all nodes, limiting the rate of task starts of a group.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TokenBucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TokenBucket {
+    #[must_use]
+    pub fn full(config: &RateLimitConfig, now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: f64::from(config.burst),
+            updated_at: now,
+        }
+    }
+
+    /// Replenishes tokens for the time passed since the last update.
+    #[must_use]
+    pub fn refill(self, config: &RateLimitConfig, now: DateTime<Utc>) -> Self {
+        let elapsed = (now - self.updated_at).to_std().unwrap_or_default();
+        let refilled = elapsed.as_secs_f64() * f64::from(config.per_minute) / 60.0;
+
+        Self {
+            tokens: (self.tokens + refilled).min(f64::from(config.burst)),
+            updated_at: now.max(self.updated_at),
+        }
+    }
+
+    /// Takes a single token, returning `None` if the bucket is empty.
+    #[must_use]
+    pub fn take(self) -> Option<Self> {
+        (self.tokens >= 1.0).then_some(Self {
+            tokens: self.tokens - 1.0,
+            ..self
+        })
+    }
+}
+
+/// Holds the rate limit configuration of a group.
+#[derive(Debug, Clone)]
+pub struct RateLimitKey<'a> {
+    pub group: &'a ConcurrencyGroup,
+}
+
+impl<'a> RateLimitKey<'a> {
+    pub const PREFIX: u8 = b'l';
+
+    #[must_use]
+    pub fn new(group: &'a ConcurrencyGroup) -> Self {
+        Self { group }
+    }
+
+    pub fn group_from_key(key: &[u8]) -> anyhow::Result<ConcurrencyGroup> {
+        let [Self::PREFIX, group @ ..] = key else {
+            bail!("unexpected prefix byte");
+        };
+
+        ConcurrencyGroup::from_str(std::str::from_utf8(group)?)
+    }
+}
+
+impl From<&RateLimitKey<'_>> for Vec<u8> {
+    fn from(value: &RateLimitKey<'_>) -> Self {
+        let mut result = vec![RateLimitKey::PREFIX];
+        result.extend_from_slice(value.group.inner().as_bytes());
+        result
+    }
+}
+
+/// Holds the token bucket state of a rate limited group.
+#[derive(Debug, Clone)]
+pub struct TokenBucketKey<'a> {
+    pub group: &'a ConcurrencyGroup,
+}
+
+impl<'a> TokenBucketKey<'a> {
+    pub const PREFIX: u8 = b'b';
+
+    #[must_use]
+    pub fn new(group: &'a ConcurrencyGroup) -> Self {
+        Self { group }
+    }
+}
+
+impl From<&TokenBucketKey<'_>> for Vec<u8> {
+    fn from(value: &TokenBucketKey<'_>) -> Self {
+        let mut result = vec![TokenBucketKey::PREFIX];
+        result.extend_from_slice(value.group.inner().as_bytes());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    const CONFIG: RateLimitConfig = RateLimitConfig {
+        per_minute: 60,
+        burst: 3,
+    };
+
+    fn bucket(tokens: f64) -> TokenBucket {
+        TokenBucket {
+            tokens,
+            updated_at: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn refill_replenishes_per_minute() {
+        let now = DateTime::UNIX_EPOCH + TimeDelta::seconds(2);
+        let refilled = bucket(0.0).refill(&CONFIG, now);
+
+        assert_eq!(refilled.tokens, 2.0);
+        assert_eq!(refilled.updated_at, now);
+    }
+
+    #[test]
+    fn refill_caps_at_burst() {
+        let now = DateTime::UNIX_EPOCH + TimeDelta::minutes(10);
+
+        assert_eq!(bucket(1.0).refill(&CONFIG, now).tokens, 3.0);
+    }
+
+    #[test]
+    fn refill_ignores_clock_going_backwards() {
+        let now = DateTime::UNIX_EPOCH - TimeDelta::minutes(1);
+        let refilled = bucket(1.5).refill(&CONFIG, now);
+
+        assert_eq!(refilled, bucket(1.5));
+    }
+
+    #[test]
+    fn take_removes_one_token() {
+        assert_eq!(bucket(1.5).take(), Some(bucket(0.5)));
+    }
+
+    #[test]
+    fn take_from_empty_bucket() {
+        assert_eq!(bucket(0.0).take(), None);
+        assert_eq!(bucket(0.99).take(), None);
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+/// Resources requested by a task, or provided by a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Resources {
+    pub cpu: u32,
+    pub memory: u32,
+}
+
+impl Resources {
+    pub const ZERO: Self = Self { cpu: 0, memory: 0 };
+
+    #[must_use]
+    pub fn new(cpu: u32, memory: u32) -> Self {
+        Self { cpu, memory }
+    }
+
+    /// Whether the given request fits into these resources.
+    #[must_use]
+    pub fn fits(&self, request: &Resources) -> bool {
+        request.cpu <= self.cpu && request.memory <= self.memory
+    }
+
+    #[must_use]
+    pub fn saturating_add(self, other: Resources) -> Self {
+        Self {
+            cpu: self.cpu.saturating_add(other.cpu),
+            memory: self.memory.saturating_add(other.memory),
+        }
+    }
+
+    #[must_use]
+    pub fn saturating_sub(self, other: Resources) -> Self {
+        Self {
+            cpu: self.cpu.saturating_sub(other.cpu),
+            memory: self.memory.saturating_sub(other.memory),
+        }
+    }
+}
+
+/// Tasks request a single CPU and no memory unless specified otherwise.
+impl Default for Resources {
+    fn default() -> Self {
+        Self { cpu: 1, memory: 0 }
+    }
+}
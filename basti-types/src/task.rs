@@ -1,17 +1,68 @@
 use std::{fmt::Display, str::FromStr, time::Duration};
 
 use anyhow::bail;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 use uuid::Uuid;
 
-use crate::WorkerName;
+use crate::{ConcurrencyGroup, Labels, Resources, WorkerName};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateTask {
     pub duration: Duration,
     pub priority: TaskPriority,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedupe_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unique_key: Option<String>,
+    #[serde(default)]
+    pub on_conflict: ConflictPolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency_group: Option<ConcurrencyGroup>,
+    /// Labels a worker needs to have to run the task.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub selector: Labels,
+    #[serde(default)]
+    pub resources: Resources,
+    /// Interval at which the task reports progress. Defaults to the one of
+    /// the node the task is submitted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<Duration>,
+    /// Time without progress after which the running task is requeued.
+    /// Defaults to the one of the node the task is submitted to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_timeout: Option<Duration>,
+    /// Maximum wall-clock time the task may take, counted from its first start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    /// Time after which the task fails if it hasn't been started yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    /// Opaque input of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// User-defined metadata, which tasks can be filtered by.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+/// What to do when a task is submitted while another task with the same
+/// unique key exists.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, Display,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Reject,
+    Coalesce,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateTask {
+    pub priority: TaskPriority,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,10 +75,10 @@ pub struct Task {
 
 impl Task {
     #[must_use]
-    pub fn generate(priority: TaskPriority, duration: Duration) -> Self {
+    pub fn generate(payload: CreateTask) -> Self {
         Self {
             key: TaskKey::generate(),
-            value: TaskValue::new_with_current_time(duration, priority),
+            value: TaskValue::new_with_current_time(payload),
         }
     }
 }
@@ -77,7 +128,7 @@ impl TryFrom<&[u8]> for TaskKey {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TaskValue {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub assignee: Option<WorkerName>,
@@ -86,38 +137,195 @@ pub struct TaskValue {
     pub priority: TaskPriority,
     pub created_at: DateTime<Utc>,
     pub duration: Duration,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dedupe_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency_group: Option<ConcurrencyGroup>,
+    /// Slot of the concurrency group held while the task is running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency_slot: Option<u32>,
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub selector: Labels,
+    #[serde(default)]
+    pub resources: Resources,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_timeout: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the task was last queued, on creation or when it was requeued.
+    /// Missing for tasks queued before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_at: Option<DateTime<Utc>>,
+    /// Number of times the task was acquired by a worker.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Why the task failed, set once it is failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<TaskOutcome>,
+    /// Value returned by the task, set once it is finished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// Opaque input of the task, passed to its handler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// User-defined metadata, which tasks can be filtered by.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+    /// Fraction of the task which is done, as last reported by its handler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f32>,
+    /// State of the task's handler at its last checkpoint, from which it
+    /// resumes when the task is started again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<serde_json::Value>,
 }
 
 impl TaskValue {
+    /// Maximum size of a task's payload when encoded as JSON.
+    pub const MAX_PAYLOAD_SIZE: usize = 64 * 1024;
+    /// Maximum size of a task's result when encoded as JSON.
+    pub const MAX_RESULT_SIZE: usize = 64 * 1024;
+    /// Maximum size of a task's checkpoint when encoded as JSON.
+    pub const MAX_CHECKPOINT_SIZE: usize = 64 * 1024;
+
     #[must_use]
-    pub fn new_with_current_time(duration: Duration, priority: TaskPriority) -> Self {
+    pub fn new_with_current_time(payload: CreateTask) -> Self {
         let now = Utc::now();
         Self {
-            duration,
-            remaining: duration,
+            duration: payload.duration,
+            remaining: payload.duration,
             created_at: now,
             updated_at: now,
-            priority,
+            priority: payload.priority,
             assignee: None,
+            dedupe_key: payload.dedupe_key,
+            unique_key: payload.unique_key,
+            concurrency_group: payload.concurrency_group,
+            concurrency_slot: None,
+            selector: payload.selector,
+            resources: payload.resources,
+            heartbeat_interval: payload.heartbeat_interval,
+            stale_timeout: payload.stale_timeout,
+            timeout: payload.timeout,
+            deadline: payload.deadline,
+            started_at: None,
+            queued_at: Some(now),
+            attempts: 0,
+            outcome: None,
+            result: None,
+            payload: payload.payload,
+            labels: payload.labels,
+            progress: None,
+            checkpoint: None,
+        }
+    }
+
+    /// Time at which the task exceeds its timeout, if it has one and started.
+    #[must_use]
+    pub fn timeout_at(&self) -> Option<DateTime<Utc>> {
+        let timeout = TimeDelta::from_std(self.timeout?).ok()?;
+        self.started_at?.checked_add_signed(timeout)
+    }
+
+    /// Whether the task missed its deadline, i.e. wasn't started before it.
+    /// Tasks which were started and requeued since have met their deadline.
+    #[must_use]
+    pub fn past_deadline(&self, now: DateTime<Utc>) -> bool {
+        self.started_at.is_none() && self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+/// Why a task failed.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, EnumString, Display,
+)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TaskOutcome {
+    /// The task ran longer than its timeout.
+    TimedOut,
+    /// The task wasn't started before its deadline.
+    DeadlineExceeded,
+    /// The task returned a result larger than the maximum result size.
+    ResultTooLarge,
+    /// The task's handler returned the given error.
+    HandlerFailed(String),
+}
+
+/// What became of a task submitted in a batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Created {
+        task: Task,
+    },
+    /// A task with the same dedupe key or a coalescable unique key exists.
+    Existing {
+        task: Task,
+    },
+    /// The task wasn't created, as another task holds its unique key.
+    Conflict {
+        existing: Uuid,
+    },
+}
+
+/// Result of a task, or why it failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub id: Uuid,
+    pub state: TaskState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<TaskOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+impl From<Task> for TaskResult {
+    fn from(task: Task) -> Self {
+        Self {
+            id: task.key.id,
+            state: task.key.state,
+            outcome: task.value.outcome,
+            result: task.value.result,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumString, Display,
+)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum TaskState {
+    #[default]
     Queued,
     Running,
+    Paused,
+    Failed,
+    Finished,
 }
 
 impl TaskState {
-    pub const VARIANTS: [Self; 2] = [Self::Queued, Self::Running];
-}
+    pub const VARIANTS: [Self; 5] = [
+        Self::Queued,
+        Self::Running,
+        Self::Paused,
+        Self::Failed,
+        Self::Finished,
+    ];
 
-impl Default for TaskState {
-    fn default() -> Self {
-        Self::Queued
+    /// Whether tasks in this state are done and won't change anymore.
+    #[must_use]
+    pub fn is_terminal(self) -> bool {
+        matches!(self, Self::Failed | Self::Finished)
     }
 }
 
@@ -126,6 +334,9 @@ impl From<TaskState> for u8 {
         match value {
             TaskState::Queued => b'q',
             TaskState::Running => b'r',
+            TaskState::Paused => b'p',
+            TaskState::Failed => b'f',
+            TaskState::Finished => b'd',
         }
     }
 }
@@ -136,6 +347,9 @@ impl TryFrom<u8> for TaskState {
         Ok(match value {
             b'q' => Self::Queued,
             b'r' => Self::Running,
+            b'p' => Self::Paused,
+            b'f' => Self::Failed,
+            b'd' => Self::Finished,
             _ => bail!("unexpected state byte"),
         })
     }
@@ -203,3 +417,74 @@ impl TryFrom<&[u8]> for PriorityKey {
         Ok(Self::new(TaskPriority(*priority), Uuid::from_slice(uuid)?))
     }
 }
+
+/// Marks a running task to be paused by its assignee at the next checkpoint.
+#[derive(Debug, Clone, Copy)]
+pub struct PauseRequestKey {
+    pub id: Uuid,
+}
+
+impl PauseRequestKey {
+    pub const PREFIX: u8 = b'h';
+
+    #[must_use]
+    pub fn new(id: Uuid) -> Self {
+        Self { id }
+    }
+}
+
+impl From<&PauseRequestKey> for Vec<u8> {
+    fn from(value: &PauseRequestKey) -> Self {
+        let mut result = vec![PauseRequestKey::PREFIX];
+        result.extend_from_slice(value.id.as_bytes());
+        result
+    }
+}
+
+/// Maps a client-provided deduplication key to the ID of the task it created.
+#[derive(Debug, Clone, Copy)]
+pub struct DedupeKey<'a> {
+    pub key: &'a str,
+}
+
+impl<'a> DedupeKey<'a> {
+    pub const PREFIX: u8 = b'd';
+    pub const MAX_LENGTH: usize = 128;
+
+    #[must_use]
+    pub fn new(key: &'a str) -> Self {
+        Self { key }
+    }
+}
+
+impl From<&DedupeKey<'_>> for Vec<u8> {
+    fn from(value: &DedupeKey<'_>) -> Self {
+        let mut result = vec![DedupeKey::PREFIX];
+        result.extend_from_slice(value.key.as_bytes());
+        result
+    }
+}
+
+/// Maps a unique key to the ID of the single non-terminal task holding it.
+#[derive(Debug, Clone, Copy)]
+pub struct UniqueKey<'a> {
+    pub key: &'a str,
+}
+
+impl<'a> UniqueKey<'a> {
+    pub const PREFIX: u8 = b'u';
+    pub const MAX_LENGTH: usize = 128;
+
+    #[must_use]
+    pub fn new(key: &'a str) -> Self {
+        Self { key }
+    }
+}
+
+impl From<&UniqueKey<'_>> for Vec<u8> {
+    fn from(value: &UniqueKey<'_>) -> Self {
+        let mut result = vec![UniqueKey::PREFIX];
+        result.extend_from_slice(value.key.as_bytes());
+        result
+    }
+}